@@ -0,0 +1,221 @@
+use crate::error::{CosprmError, Result};
+use crc::{Crc, CRC_64_XZ};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The folder inside the data_win32 directory that holds every backup snapshot
+pub const BACKUP_DIR: &str = ".cosprm-backups";
+
+const MANIFEST_FILE: &str = "manifest.json";
+
+/// Describes a backup snapshot: which files were copied and what they looked like
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupManifest {
+    /// Name of the snapshot folder
+    pub snapshot: String,
+    /// Seconds since the unix epoch when the backup was made
+    pub timestamp: u64,
+    pub files: Vec<BackupFile>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupFile {
+    /// Path relative to the data_win32 directory
+    pub path: String,
+    /// Hash of the file before cosprm modified it
+    pub original_hash: String,
+    /// Hash of the file cosprm wrote, used to detect changes made by something else since
+    pub written_hash: Option<String>,
+}
+
+impl BackupManifest {
+    /// Copy the files into a new snapshot folder that mirrors the data_win32 layout
+    pub fn create(directory: &Path, files: &[String]) -> Result<Self> {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or_default();
+
+        // Two runs in the same second get their own snapshot
+        let mut snapshot = timestamp.to_string();
+        let mut suffix = 1;
+        while snapshot_dir(directory, &snapshot).exists() {
+            snapshot = format!("{}-{}", timestamp, suffix);
+            suffix += 1;
+        }
+
+        let mut manifest = BackupManifest {
+            snapshot,
+            timestamp,
+            files: Vec::new(),
+        };
+
+        for file in files {
+            let relative = relative_path(directory, file);
+            let backup_file = manifest.dir(directory).join(&relative);
+
+            if let Some(parent) = backup_file.parent() {
+                fs::create_dir_all(parent).map_err(|source| CosprmError::io(parent, source))?;
+            }
+            fs::copy(file, &backup_file).map_err(|source| CosprmError::io(&backup_file, source))?;
+
+            manifest.files.push(BackupFile {
+                path: relative.to_string_lossy().to_string(),
+                original_hash: hash_file(Path::new(file))?,
+                written_hash: None,
+            });
+        }
+
+        manifest.write(directory)?;
+
+        Ok(manifest)
+    }
+
+    /// Record the hashes of the files as cosprm wrote them
+    pub fn record_written(&mut self, directory: &Path) -> Result<()> {
+        for file in &mut self.files {
+            file.written_hash = Some(hash_file(&directory.join(&file.path))?);
+        }
+
+        self.write(directory)
+    }
+
+    /// Delete the snapshot, used when nothing ended up being written
+    pub fn discard(&self, directory: &Path) {
+        let _ = fs::remove_dir_all(self.dir(directory));
+    }
+
+    /// Every snapshot in the directory, oldest first
+    pub fn list(directory: &Path) -> Result<Vec<Self>> {
+        let backup_dir = directory.join(BACKUP_DIR);
+
+        if !backup_dir.exists() {
+            return Ok(Vec::new());
+        }
+
+        let mut manifests = Vec::new();
+
+        for entry in
+            fs::read_dir(&backup_dir).map_err(|source| CosprmError::io(&backup_dir, source))?
+        {
+            let entry = entry.map_err(|source| CosprmError::io(&backup_dir, source))?;
+            let manifest_file = entry.path().join(MANIFEST_FILE);
+
+            if manifest_file.exists() {
+                manifests.push(Self::read(&manifest_file)?);
+            }
+        }
+
+        manifests.sort_by(|a, b| {
+            a.timestamp
+                .cmp(&b.timestamp)
+                .then(a.snapshot.cmp(&b.snapshot))
+        });
+
+        Ok(manifests)
+    }
+
+    pub fn load(directory: &Path, snapshot: &str) -> Result<Self> {
+        let manifest_file = snapshot_dir(directory, snapshot).join(MANIFEST_FILE);
+
+        if !manifest_file.exists() {
+            return Err(CosprmError::BackupNotFound {
+                snapshot: snapshot.to_string(),
+            });
+        }
+
+        Self::read(&manifest_file)
+    }
+
+    /// Copy the backed up files over the current ones.
+    /// Unless `force` is set, this refuses when a file was changed since cosprm wrote it
+    pub fn restore(&self, directory: &Path, force: bool) -> Result<Vec<String>> {
+        if !force {
+            let mut changed_files = Vec::new();
+
+            for file in &self.files {
+                let current_file = directory.join(&file.path);
+
+                let unchanged = match &file.written_hash {
+                    Some(written_hash) => {
+                        current_file.exists() && &hash_file(&current_file)? == written_hash
+                    }
+                    None => false,
+                };
+
+                if !unchanged {
+                    changed_files.push(file.path.clone());
+                }
+            }
+
+            if !changed_files.is_empty() {
+                return Err(CosprmError::BackupConflict {
+                    snapshot: self.snapshot.clone(),
+                    files: changed_files,
+                });
+            }
+        }
+
+        let mut restored_files = Vec::new();
+
+        for file in &self.files {
+            let backup_file = self.dir(directory).join(&file.path);
+            let current_file = directory.join(&file.path);
+
+            // Copy next to the file first so it's never left half written
+            let staged_file = PathBuf::from(format!("{}.cosprm-staged", current_file.display()));
+            fs::copy(&backup_file, &staged_file)
+                .map_err(|source| CosprmError::io(&backup_file, source))?;
+            fs::rename(&staged_file, &current_file)
+                .map_err(|source| CosprmError::io(&current_file, source))?;
+
+            restored_files.push(current_file.display().to_string());
+        }
+
+        Ok(restored_files)
+    }
+
+    fn dir(&self, directory: &Path) -> PathBuf {
+        snapshot_dir(directory, &self.snapshot)
+    }
+
+    fn read(manifest_file: &Path) -> Result<Self> {
+        let json_str = fs::read_to_string(manifest_file)
+            .map_err(|source| CosprmError::io(manifest_file, source))?;
+
+        serde_json::from_str(&json_str).map_err(|source| CosprmError::ConfigParse {
+            path: manifest_file.display().to_string(),
+            source,
+        })
+    }
+
+    fn write(&self, directory: &Path) -> Result<()> {
+        let manifest_file = self.dir(directory).join(MANIFEST_FILE);
+
+        let json_str = serde_json::to_string_pretty(self)
+            .map_err(|source| CosprmError::json_write(&manifest_file, source))?;
+
+        fs::write(&manifest_file, json_str)
+            .map_err(|source| CosprmError::io(&manifest_file, source))
+    }
+}
+
+fn snapshot_dir(directory: &Path, snapshot: &str) -> PathBuf {
+    directory.join(BACKUP_DIR).join(snapshot)
+}
+
+fn relative_path(directory: &Path, file: &str) -> PathBuf {
+    Path::new(file)
+        .strip_prefix(directory)
+        .map(|path| path.to_path_buf())
+        .unwrap_or_else(|_| PathBuf::from(Path::new(file).file_name().unwrap_or_default()))
+}
+
+fn hash_file(file: &Path) -> Result<String> {
+    let data = fs::read(file).map_err(|source| CosprmError::io(file, source))?;
+
+    let crc = Crc::<u64>::new(&CRC_64_XZ);
+    Ok(format!("{:016x}", crc.checksum(&data)))
+}
@@ -0,0 +1,46 @@
+use crate::error::{CosprmError, Result};
+use crate::param::nucc_binary_handler::get_nucc_binaries;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+
+/// Copy the xfbins holding param tables from a pristine vanilla directory into the output
+/// directory, keeping their layout. Files already in the output are overwritten, so every
+/// build starts from the vanilla tables. Returns the paths of the copied files
+pub fn copy_vanilla_files(vanilla: &Path, output: &Path) -> Result<Vec<String>> {
+    if is_same_directory(vanilla, output) {
+        return Err(CosprmError::BuildIntoVanilla {
+            directory: output.display().to_string(),
+        });
+    }
+
+    let files = get_nucc_binaries(vanilla)?
+        .into_keys()
+        .map(|key| key.filepath)
+        .collect::<BTreeSet<_>>();
+
+    let mut copied_files = Vec::new();
+
+    for file in files {
+        let relative = Path::new(&file)
+            .strip_prefix(vanilla)
+            .unwrap_or(Path::new(&file));
+        let output_file = output.join(relative);
+
+        if let Some(parent) = output_file.parent() {
+            fs::create_dir_all(parent).map_err(|source| CosprmError::io(parent, source))?;
+        }
+        fs::copy(&file, &output_file).map_err(|source| CosprmError::io(&output_file, source))?;
+
+        copied_files.push(output_file.display().to_string());
+    }
+
+    Ok(copied_files)
+}
+
+fn is_same_directory(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
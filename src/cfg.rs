@@ -1,27 +1,278 @@
+use crate::error::{CosprmError, Result};
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::path::Path;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CostumeAddConfig {
     pub costumes: Vec<CostumeConfig>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CostumeConfig {
+    /// The next free model index of the character is picked when omitted or 0
+    #[serde(default)]
     pub model_index: i32,
     pub characode: String,
     pub modelcode: String,
     pub iconcode: String,
+    /// The next free c_cha_NNN is picked when omitted or empty
+    #[serde(default)]
     pub cha_id: String,
     pub char_name: String,
+    /// The next free c_costume_NNN is picked when omitted or empty
+    #[serde(default)]
     pub costume_id: String,
     pub costume_name: String,
     pub color_count: i32,
+    /// Whether the costume tears in battle, `costume_break` takes precedence when it's set
+    #[serde(default)]
     pub has_costume_break: bool,
+    /// The CostumeBreakParam entry of the costume, for more than turning it on or off
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub costume_break: Option<CostumeBreakConfig>,
+    /// Per-language names keyed by the MessageInfo language folder (e.g. "eng", "jpn")
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub names: HashMap<String, LocalizedNames>,
+    /// Shop price of every color, free when omitted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub price: Option<u32>,
+    /// Unlock condition of every color, unlocked from the start when omitted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unlock_condition: Option<u32>,
+    /// Overrides for single colors
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub colors: Vec<ColorConfig>,
+    /// The existing entries new rows are cloned from, instead of picking them automatically
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub template: Option<TemplateConfig>,
 }
 
+/// Overrides `char_name` and `costume_name` for a single language
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LocalizedNames {
+    pub char_name: Option<String>,
+    pub costume_name: Option<String>,
+}
+
+/// Overrides the costume's price and unlock condition for one color index
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ColorConfig {
+    pub color_index: u32,
+    pub price: Option<u32>,
+    pub unlock_condition: Option<u32>,
+}
+
+/// The CostumeBreakParam entry of a costume. The table has one entry per costume,
+/// so the settings apply to every color
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CostumeBreakConfig {
+    /// Whether the costume gets a CostumeBreakParam entry
+    #[serde(default = "enabled_by_default")]
+    pub enabled: bool,
+    /// The modelcode of the torn model that's swapped in, the cloned entry's is kept when omitted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub broken_modelcode: Option<String>,
+    /// Other fields of the entry by name, set on the cloned entry as they are
+    #[serde(default, skip_serializing_if = "Map::is_empty")]
+    pub fields: Map<String, Value>,
+}
+
+impl Default for CostumeBreakConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            broken_modelcode: None,
+            fields: Map::new(),
+        }
+    }
+}
+
+fn enabled_by_default() -> bool {
+    true
+}
+
+/// Names the entry each table's new rows are cloned from. Tables that aren't set
+/// keep using the character's latest or base entry
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TemplateConfig {
+    /// The message id (e.g. "c_costume_011") of the MessageInfo row to clone
+    pub message_info: Option<String>,
+    pub player_setting_param: Option<PlayerSettingSelector>,
+    /// The costume_link (e.g. "COSTUME_00350") of the CostumeParam row to clone
+    pub costume_param: Option<String>,
+    /// Clones the icon of the costume the PlayerSettingParam row belongs to
+    pub player_icon: Option<PlayerSettingSelector>,
+    /// Clones the character select row with the PlayerSettingParam row's searchcode
+    pub character_select_param: Option<PlayerSettingSelector>,
+    /// Clones the costume break row of the costume the PlayerSettingParam row belongs to
+    pub costume_break_param: Option<PlayerSettingSelector>,
+}
+
+/// A PlayerSettingParam row, either by player_setting_id or by searchcode (e.g. "3mnt00")
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum PlayerSettingSelector {
+    Id(u32),
+    Searchcode(String),
+}
+
+impl std::fmt::Display for PlayerSettingSelector {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PlayerSettingSelector::Id(id) => write!(f, "player_setting_id {}", id),
+            PlayerSettingSelector::Searchcode(searchcode) => write!(f, "searchcode {}", searchcode),
+        }
+    }
+}
+
+pub const DEFAULT_PRICE: u32 = 0;
+pub const DEFAULT_UNLOCK_CONDITION: u32 = 1;
+
 impl CostumeAddConfig {
-    pub fn read_cfg(filepath: &str) -> Self {
-        let json_str = std::fs::read_to_string(filepath).unwrap();
-        serde_json::from_str(&json_str).unwrap()
+    pub fn read_cfg(filepath: &str) -> Result<Self> {
+        let json_str =
+            std::fs::read_to_string(filepath).map_err(|source| CosprmError::ConfigRead {
+                path: filepath.to_string(),
+                source,
+            })?;
+
+        serde_json::from_str(&json_str).map_err(|source| CosprmError::ConfigParse {
+            path: filepath.to_string(),
+            source,
+        })
+    }
+
+    pub fn write_cfg(&self, filepath: &str) -> Result<()> {
+        let json_str = serde_json::to_string_pretty(self)
+            .map_err(|source| CosprmError::json_write(filepath, source))?;
+
+        std::fs::write(filepath, json_str).map_err(|source| CosprmError::io(filepath, source))
+    }
+
+    /// Fill in the model indexes and ids that were left unset with the ones `assigned` picked,
+    /// matching costumes by characode and modelcode. Returns whether anything changed
+    pub fn fill_assigned_ids(&mut self, assigned: &CostumeAddConfig) -> bool {
+        let mut changed = false;
+
+        for costume in self.costumes.iter_mut().filter(|c| c.has_unset_ids()) {
+            let other = match assigned.costumes.iter().find(|other| {
+                other.characode == costume.characode && other.modelcode == costume.modelcode
+            }) {
+                Some(other) => other,
+                None => continue,
+            };
+
+            if costume.model_index == 0 {
+                costume.model_index = other.model_index;
+            }
+            if costume.cha_id.is_empty() {
+                costume.cha_id = other.cha_id.clone();
+            }
+            if costume.costume_id.is_empty() {
+                costume.costume_id = other.costume_id.clone();
+            }
+
+            changed = true;
+        }
+
+        changed
+    }
+
+    /// The .json files in a directory, in file name order
+    pub fn config_files(directory: &Path) -> Result<Vec<String>> {
+        let read_dir = std::fs::read_dir(directory).map_err(|source| CosprmError::ConfigRead {
+            path: directory.display().to_string(),
+            source,
+        })?;
+
+        let mut files = read_dir
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.is_file() && path.extension().map_or(false, |ext| ext == "json"))
+            .map(|path| path.display().to_string())
+            .collect::<Vec<_>>();
+        files.sort();
+
+        Ok(files)
+    }
+}
+
+impl CostumeConfig {
+    /// A short description of the costume used in messages
+    pub fn label(&self) -> String {
+        let id = if self.cha_id.is_empty() {
+            &self.modelcode
+        } else {
+            &self.cha_id
+        };
+
+        format!(
+            "{} (characode {}, model index {})",
+            id, self.characode, self.model_index
+        )
+    }
+
+    /// Identifies the costume in the lockfile, both for the ids cosprm picked and the ones it allocated
+    pub fn lock_key(&self) -> String {
+        format!("{}/{}", self.characode, self.modelcode)
+    }
+
+    /// Whether cosprm has to pick the model index, cha_id or costume_id
+    pub fn has_unset_ids(&self) -> bool {
+        self.model_index == 0 || self.cha_id.is_empty() || self.costume_id.is_empty()
+    }
+
+    /// Whether the costume gets a CostumeBreakParam entry
+    pub fn costume_break_enabled(&self) -> bool {
+        self.costume_break
+            .as_ref()
+            .map_or(self.has_costume_break, |costume_break| {
+                costume_break.enabled
+            })
+    }
+
+    /// The character name for a language, falling back to `char_name`
+    pub fn char_name_for(&self, language: Option<&str>) -> &str {
+        language
+            .and_then(|language| self.names.get(language))
+            .and_then(|names| names.char_name.as_deref())
+            .unwrap_or(&self.char_name)
+    }
+
+    /// The price of a color: the color override, then the costume's price, then free
+    pub fn price_for(&self, color_index: u32) -> u32 {
+        self.color(color_index)
+            .and_then(|color| color.price)
+            .or(self.price)
+            .unwrap_or(DEFAULT_PRICE)
+    }
+
+    /// The unlock condition of a color: the color override, then the costume's, then the default
+    pub fn unlock_condition_for(&self, color_index: u32) -> u32 {
+        self.color(color_index)
+            .and_then(|color| color.unlock_condition)
+            .or(self.unlock_condition)
+            .unwrap_or(DEFAULT_UNLOCK_CONDITION)
+    }
+
+    /// The template config, empty when the costume doesn't have one
+    pub fn template(&self) -> TemplateConfig {
+        self.template.clone().unwrap_or_default()
+    }
+
+    fn color(&self, color_index: u32) -> Option<&ColorConfig> {
+        self.colors
+            .iter()
+            .find(|color| color.color_index == color_index)
+    }
+
+    /// The costume name for a language, falling back to `costume_name`
+    pub fn costume_name_for(&self, language: Option<&str>) -> &str {
+        language
+            .and_then(|language| self.names.get(language))
+            .and_then(|names| names.costume_name.as_deref())
+            .unwrap_or(&self.costume_name)
     }
 }
@@ -0,0 +1,247 @@
+use crate::merge::ConfigConflict;
+use crate::validate::ValidationIssue;
+use nuccbin::NuccBinaryType;
+use std::fmt;
+use std::path::Path;
+
+pub type Result<T> = std::result::Result<T, CosprmError>;
+
+#[derive(Debug)]
+pub enum CosprmError {
+    /// A required command line argument wasn't passed
+    MissingArgument { name: String },
+    /// The build output directory is the vanilla directory itself
+    BuildIntoVanilla { directory: String },
+    /// The costume config couldn't be read from disk
+    ConfigRead {
+        path: String,
+        source: std::io::Error,
+    },
+    /// The costume config isn't valid JSON or is missing fields
+    ConfigParse {
+        path: String,
+        source: serde_json::Error,
+    },
+    /// The costume config failed validation
+    InvalidConfig { issues: Vec<ValidationIssue> },
+    /// Several configs claim the same costume slot or ids
+    ConfigConflict { conflicts: Vec<ConfigConflict> },
+    /// An exported table that was edited can't be imported back
+    InvalidTableFile { path: String, message: String },
+    /// An xfbin couldn't be read or parsed
+    XfbinRead { path: String, message: String },
+    /// An xfbin couldn't be written
+    XfbinWrite { path: String, message: String },
+    /// A file operation failed
+    Io {
+        path: String,
+        source: std::io::Error,
+    },
+    /// A config, lockfile or report couldn't be serialized to JSON
+    JsonWrite {
+        path: String,
+        source: serde_json::Error,
+    },
+    /// The directory didn't contain any of the param tables
+    NoNuccBinaries { directory: String },
+    /// A table that's needed wasn't loaded from the directory
+    MissingTable { nucc_type: NuccBinaryType },
+    /// An entry a costume depends on couldn't be found in a table
+    EntryNotFound {
+        nucc_type: NuccBinaryType,
+        costume: String,
+        description: String,
+    },
+    /// A CostumeParam costume_link doesn't end in a number
+    InvalidCostumeLink { costume_link: String },
+    /// Fields a config sets on an entry don't fit the table
+    InvalidOverride {
+        nucc_type: NuccBinaryType,
+        costume: String,
+        message: String,
+    },
+    /// A characode already uses every two digit searchcode
+    SearchcodesExhausted { characode: String },
+    /// The DuelPlayerParam slot of a model index already loads another model
+    ModelSlotTaken {
+        characode: String,
+        model_index: i32,
+        model: String,
+    },
+    /// The param tables reference rows that don't exist, or have duplicates
+    IntegrityCheckFailed { errors: usize, warnings: usize },
+    /// There is no backup snapshot with that name
+    BackupNotFound { snapshot: String },
+    /// Files were changed by something else since the backup was made
+    BackupConflict {
+        snapshot: String,
+        files: Vec<String>,
+    },
+}
+
+impl CosprmError {
+    pub fn entry_not_found(
+        nucc_type: NuccBinaryType,
+        costume: impl Into<String>,
+        description: impl Into<String>,
+    ) -> Self {
+        CosprmError::EntryNotFound {
+            nucc_type,
+            costume: costume.into(),
+            description: description.into(),
+        }
+    }
+
+    /// A failed file operation on `path`
+    pub fn io(path: impl AsRef<Path>, source: std::io::Error) -> Self {
+        CosprmError::Io {
+            path: path.as_ref().display().to_string(),
+            source,
+        }
+    }
+
+    /// Serializing the JSON for `path` failed, nothing was written to it
+    pub fn json_write(path: impl AsRef<Path>, source: serde_json::Error) -> Self {
+        CosprmError::JsonWrite {
+            path: path.as_ref().display().to_string(),
+            source,
+        }
+    }
+
+    /// The process exit code for the error, so scripts can tell failures apart
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            CosprmError::MissingArgument { .. } | CosprmError::BuildIntoVanilla { .. } => 2,
+            CosprmError::ConfigRead { .. }
+            | CosprmError::ConfigParse { .. }
+            | CosprmError::InvalidConfig { .. }
+            | CosprmError::ConfigConflict { .. }
+            | CosprmError::InvalidTableFile { .. }
+            | CosprmError::InvalidOverride { .. } => 3,
+            CosprmError::XfbinRead { .. }
+            | CosprmError::XfbinWrite { .. }
+            | CosprmError::Io { .. }
+            | CosprmError::JsonWrite { .. } => 4,
+            CosprmError::NoNuccBinaries { .. } | CosprmError::MissingTable { .. } => 5,
+            CosprmError::EntryNotFound { .. }
+            | CosprmError::InvalidCostumeLink { .. }
+            | CosprmError::SearchcodesExhausted { .. }
+            | CosprmError::ModelSlotTaken { .. } => 6,
+            CosprmError::BackupNotFound { .. } | CosprmError::BackupConflict { .. } => 7,
+            CosprmError::IntegrityCheckFailed { .. } => 8,
+        }
+    }
+}
+
+impl fmt::Display for CosprmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CosprmError::MissingArgument { name } => {
+                write!(f, "The argument '--{}' is required", name)
+            }
+            CosprmError::BuildIntoVanilla { directory } => write!(
+                f,
+                "The output directory {} is the vanilla directory, build into a copy instead",
+                directory
+            ),
+            CosprmError::ConfigRead { path, source } => {
+                write!(f, "Failed to read config {}: {}", path, source)
+            }
+            CosprmError::ConfigParse { path, source } => {
+                write!(f, "Failed to parse config {}: {}", path, source)
+            }
+            CosprmError::InvalidConfig { issues } => {
+                write!(f, "The config has {} problem(s):", issues.len())?;
+                for issue in issues {
+                    write!(f, "\n    {}", issue)?;
+                }
+                Ok(())
+            }
+            CosprmError::ConfigConflict { conflicts } => {
+                write!(f, "The configs have {} conflict(s):", conflicts.len())?;
+                for conflict in conflicts {
+                    write!(f, "\n    {}", conflict)?;
+                }
+                Ok(())
+            }
+            CosprmError::InvalidTableFile { path, message } => {
+                write!(f, "Failed to import table {}: {}", path, message)
+            }
+            CosprmError::XfbinRead { path, message } => {
+                write!(f, "Failed to read xfbin {}: {}", path, message)
+            }
+            CosprmError::XfbinWrite { path, message } => {
+                write!(f, "Failed to write xfbin {}: {}", path, message)
+            }
+            CosprmError::Io { path, source } => write!(f, "{}: {}", path, source),
+            CosprmError::JsonWrite { path, source } => {
+                write!(f, "Failed to serialize {}: {}", path, source)
+            }
+            CosprmError::NoNuccBinaries { directory } => {
+                write!(f, "No valid NUCC binaries found in {}", directory)
+            }
+            CosprmError::MissingTable { nucc_type } => {
+                write!(f, "NUCC binary type {:?} was not loaded", nucc_type)
+            }
+            CosprmError::EntryNotFound {
+                nucc_type,
+                costume,
+                description,
+            } => write!(
+                f,
+                "{:?} for costume {}: {}",
+                nucc_type, costume, description
+            ),
+            CosprmError::InvalidCostumeLink { costume_link } => {
+                write!(f, "Costume link {} does not end in a number", costume_link)
+            }
+            CosprmError::InvalidOverride {
+                nucc_type,
+                costume,
+                message,
+            } => write!(
+                f,
+                "Invalid {:?} fields for costume {}: {}",
+                nucc_type, costume, message
+            ),
+            CosprmError::SearchcodesExhausted { characode } => {
+                write!(f, "{} has no free searchcodes left (00 to 99)", characode)
+            }
+            CosprmError::ModelSlotTaken {
+                characode,
+                model_index,
+                model,
+            } => write!(
+                f,
+                "Model index {} of {} already loads {} in DuelPlayerParam, pass --update to replace it",
+                model_index, characode, model
+            ),
+            CosprmError::IntegrityCheckFailed { errors, warnings } => write!(
+                f,
+                "The param tables have {} error(s) and {} warning(s)",
+                errors, warnings
+            ),
+            CosprmError::BackupNotFound { snapshot } => {
+                write!(f, "No backup snapshot named {}", snapshot)
+            }
+            CosprmError::BackupConflict { snapshot, files } => write!(
+                f,
+                "Files changed since snapshot {} was made (use --force to restore anyway): {}",
+                snapshot,
+                files.join(", ")
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CosprmError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CosprmError::ConfigRead { source, .. } => Some(source),
+            CosprmError::ConfigParse { source, .. } => Some(source),
+            CosprmError::Io { source, .. } => Some(source),
+            CosprmError::JsonWrite { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,404 @@
+use crate::backup::BackupManifest;
+use crate::cfg::CostumeAddConfig;
+use crate::error::{CosprmError, Result};
+use crate::lock::{AssignedIds, IdLock};
+use crate::param::handler::{HandlerContext, HandlerRegistry};
+use crate::param::message_dictionary::MessageDictionary;
+use crate::param::message_id::{next_message_id, CHA_ID_PREFIX, COSTUME_ID_PREFIX};
+use crate::param::model_index::next_model_index;
+use crate::param::{add_entry::AddOptions, nucc_binary_handler::*, report::ChangeReport};
+use crate::validate::{validate_against_tables, validate_config, ValidationIssue};
+use nuccbin::NuccBinaryType;
+use std::path::{Path, PathBuf};
+
+pub use crate::param::handler::REQUIRED_NUCC_TYPES;
+
+/// The outcome of installing or uninstalling the costumes of a config
+#[derive(Debug, Default)]
+pub struct InstallResult {
+    /// Every row that was added or removed
+    pub report: ChangeReport,
+    /// Required tables that weren't found in the directory and were skipped
+    pub missing_nucc_types: Vec<NuccBinaryType>,
+    /// Model indexes and ids that were picked for costumes that left them unset
+    pub assigned: Vec<AssignedId>,
+    /// Indexes of the costumes in the config that were left alone because their model index or
+    /// ids are unset and the lockfile doesn't record them
+    pub skipped: Vec<usize>,
+}
+
+/// A model index or id cosprm picked for a costume that didn't set one
+#[derive(Debug, Clone)]
+pub struct AssignedId {
+    /// Index of the costume in the config
+    pub costume: usize,
+    pub field: &'static str,
+    pub value: String,
+}
+
+/// The outcome of saving the modified tables
+#[derive(Debug, Default)]
+pub struct SaveResult {
+    /// Paths of the xfbins that were written
+    pub written_files: Vec<String>,
+    /// The snapshot the original files were backed up to
+    pub backup: Option<BackupManifest>,
+    /// Path of the lockfile, if it was written
+    pub lock_file: Option<String>,
+}
+
+/// Loads the param tables of a data_win32 directory and applies a costume config to them.
+/// Nothing is written to disk until `save` is called
+pub struct CostumeInstaller {
+    directory: PathBuf,
+    cfg: CostumeAddConfig,
+    nucc_binaries: NuccBinaries,
+    backups: bool,
+    options: AddOptions,
+    lock: IdLock,
+    lock_path: Option<PathBuf>,
+    message_ids: MessageDictionary,
+    registry: HandlerRegistry,
+}
+
+impl CostumeInstaller {
+    pub fn new(directory: impl AsRef<Path>, cfg: CostumeAddConfig) -> Result<Self> {
+        Self::with_registry(directory, cfg, HandlerRegistry::default())
+    }
+
+    /// Install with other table handlers than the default ones. Only the tables
+    /// the handlers edit are loaded
+    pub fn with_registry(
+        directory: impl AsRef<Path>,
+        cfg: CostumeAddConfig,
+        registry: HandlerRegistry,
+    ) -> Result<Self> {
+        let directory = directory.as_ref().to_path_buf();
+        let nucc_binaries = get_nucc_binaries_of_types(&directory, &registry.nucc_types())?;
+        let message_ids = MessageDictionary::for_tables(&nucc_binaries);
+
+        Ok(Self {
+            directory,
+            cfg,
+            nucc_binaries,
+            backups: true,
+            options: AddOptions::default(),
+            lock: IdLock::default(),
+            lock_path: None,
+            message_ids,
+            registry,
+        })
+    }
+
+    /// Add the message ids of a file to the ones new ids are checked for crc32 collisions with
+    pub fn load_message_ids(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        self.message_ids.load_file(path)
+    }
+
+    /// Reuse the ids recorded in a lockfile and record newly allocated ones in it when saving.
+    /// Without a lockfile ids are allocated from the tables alone
+    pub fn set_lock_file(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        self.lock = IdLock::load(&path)?;
+        self.lock_path = Some(path.as_ref().to_path_buf());
+        Ok(())
+    }
+
+    /// Whether the original files are backed up before they're overwritten (on by default)
+    pub fn set_backups(&mut self, enabled: bool) {
+        self.backups = enabled;
+    }
+
+    /// Whether costumes that are already installed are updated to match the config
+    /// instead of being skipped (off by default)
+    pub fn set_update(&mut self, enabled: bool) {
+        self.options.update = enabled;
+    }
+
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    pub fn config(&self) -> &CostumeAddConfig {
+        &self.cfg
+    }
+
+    pub fn nucc_binaries(&self) -> &NuccBinaries {
+        &self.nucc_binaries
+    }
+
+    pub fn lock(&self) -> &IdLock {
+        &self.lock
+    }
+
+    /// Pick the model index, cha_id and costume_id of the costumes that left them unset.
+    /// The ones recorded in the lockfile for a costume are reused, new ones are recorded in it.
+    /// `install` does this itself, it's only needed to see the ids before installing
+    pub fn assign_ids(&mut self) -> Vec<AssignedId> {
+        self.resolve_ids(true)
+    }
+
+    /// Fill in the unset ids from the lockfile, allocating new ones only when `allocate` is set
+    fn resolve_ids(&mut self, allocate: bool) -> Vec<AssignedId> {
+        let mut assigned = Vec::new();
+
+        for i in 0..self.cfg.costumes.len() {
+            let costume = &self.cfg.costumes[i];
+
+            if !costume.has_unset_ids() {
+                continue;
+            }
+
+            let key = costume.lock_key();
+            let locked = self.lock.get_assigned(&key).cloned().unwrap_or_default();
+
+            // Whatever the other costumes of the config and the lockfile use is taken
+            let others = self
+                .cfg
+                .costumes
+                .iter()
+                .enumerate()
+                .filter(|(j, _)| *j != i)
+                .map(|(_, other)| other)
+                .collect::<Vec<_>>();
+            let reserved = self.lock.reserved_assigned(&key).collect::<Vec<_>>();
+
+            let character_prefix = format!("{}/", costume.characode);
+            let reserved_model_indexes = others
+                .iter()
+                .filter(|other| other.characode == costume.characode)
+                .map(|other| other.model_index)
+                .chain(
+                    reserved
+                        .iter()
+                        .filter(|(other_key, _)| other_key.starts_with(&character_prefix))
+                        .filter_map(|(_, ids)| ids.model_index),
+                )
+                .collect::<Vec<_>>();
+
+            let reserved_message_ids = others
+                .iter()
+                .flat_map(|other| [other.cha_id.as_str(), other.costume_id.as_str()])
+                .chain(reserved.iter().flat_map(|(_, ids)| {
+                    [ids.cha_id.as_deref(), ids.costume_id.as_deref()]
+                        .into_iter()
+                        .flatten()
+                }))
+                .filter(|id| !id.is_empty())
+                .collect::<Vec<_>>();
+
+            let model_index = (costume.model_index == 0)
+                .then(|| {
+                    locked
+                        .model_index
+                        .filter(|model_index| !reserved_model_indexes.contains(model_index))
+                        .or_else(|| {
+                            allocate.then(|| {
+                                next_model_index(
+                                    &self.nucc_binaries,
+                                    &costume.characode,
+                                    &reserved_model_indexes,
+                                )
+                            })
+                        })
+                })
+                .flatten();
+
+            let message_id = |current: &str, locked: &Option<String>, prefix: &str| {
+                if !current.is_empty() {
+                    return None;
+                }
+
+                locked
+                    .clone()
+                    .filter(|id| !reserved_message_ids.contains(&id.as_str()))
+                    .or_else(|| {
+                        allocate.then(|| {
+                            next_message_id(&self.nucc_binaries, prefix, &reserved_message_ids)
+                        })
+                    })
+            };
+
+            let ids = AssignedIds {
+                model_index,
+                cha_id: message_id(&costume.cha_id, &locked.cha_id, CHA_ID_PREFIX),
+                costume_id: message_id(&costume.costume_id, &locked.costume_id, COSTUME_ID_PREFIX),
+            };
+
+            let costume = &mut self.cfg.costumes[i];
+
+            if let Some(model_index) = ids.model_index {
+                costume.model_index = model_index;
+                assigned.push(AssignedId {
+                    costume: i,
+                    field: "model_index",
+                    value: model_index.to_string(),
+                });
+            }
+
+            for (field, id, value) in [
+                ("cha_id", &mut costume.cha_id, &ids.cha_id),
+                ("costume_id", &mut costume.costume_id, &ids.costume_id),
+            ] {
+                if let Some(value) = value {
+                    *id = value.clone();
+                    assigned.push(AssignedId {
+                        costume: i,
+                        field,
+                        value: value.clone(),
+                    });
+                }
+            }
+
+            if ids != AssignedIds::default() {
+                self.lock.record_assigned(&key, ids);
+            }
+        }
+
+        assigned
+    }
+
+    /// Every problem with the config, both on its own and against the loaded tables
+    pub fn validate(&self) -> Vec<ValidationIssue> {
+        let mut issues = validate_config(&self.cfg);
+        issues.extend(validate_against_tables(
+            &self.cfg,
+            &self.nucc_binaries,
+            &self.message_ids,
+            &self.lock,
+        ));
+        issues
+    }
+
+    /// Add the entries for every costume in the config.
+    /// Nothing is changed if the config doesn't pass validation
+    pub fn install(&mut self) -> Result<InstallResult> {
+        let assigned = self.assign_ids();
+
+        let issues = self.validate();
+        if !issues.is_empty() {
+            return Err(CosprmError::InvalidConfig { issues });
+        }
+
+        let mut result = InstallResult {
+            assigned,
+            ..Default::default()
+        };
+
+        for handler in self.registry.handlers() {
+            let nucc_type = handler.nucc_type();
+
+            if !has_nucc_binary(&self.nucc_binaries, nucc_type) {
+                result.missing_nucc_types.push(nucc_type);
+                continue;
+            }
+
+            let mut context = HandlerContext {
+                cfg: &self.cfg,
+                options: &self.options,
+                lock: &mut self.lock,
+                report: &mut result.report,
+            };
+
+            handler.add(&mut self.nucc_binaries, &mut context)?;
+        }
+
+        Ok(result)
+    }
+
+    /// Remove the entries that were added for every costume in the config
+    pub fn uninstall(&mut self) -> Result<InstallResult> {
+        // Costumes that left their ids unset were installed with the ones in the lockfile
+        let assigned = self.resolve_ids(false);
+
+        // Without the ids the rows of the costume can't be told apart from the ones of model index 0
+        // or other costumes, so nothing is removed for it
+        let skipped = (0..self.cfg.costumes.len())
+            .filter(|i| self.cfg.costumes[*i].has_unset_ids())
+            .collect::<Vec<_>>();
+
+        let mut cfg = self.cfg.clone();
+        cfg.costumes.retain(|costume| !costume.has_unset_ids());
+
+        let mut result = InstallResult {
+            assigned,
+            skipped,
+            ..Default::default()
+        };
+
+        for handler in self.registry.handlers().rev() {
+            let nucc_type = handler.nucc_type();
+
+            if !has_nucc_binary(&self.nucc_binaries, nucc_type) {
+                result.missing_nucc_types.push(nucc_type);
+                continue;
+            }
+
+            let mut context = HandlerContext {
+                cfg: &cfg,
+                options: &self.options,
+                lock: &mut self.lock,
+                report: &mut result.report,
+            };
+
+            handler.remove(&mut self.nucc_binaries, &mut context)?;
+        }
+
+        Ok(result)
+    }
+
+    /// Back up the files that are about to change, then write the modified tables back to them.
+    /// The lockfile is written last, once the tables it describes are saved
+    pub fn save(&mut self) -> Result<SaveResult> {
+        let mut saved = save_with_backup(&self.directory, &mut self.nucc_binaries, self.backups)?;
+
+        if let Some(lock_path) = &self.lock_path {
+            if self.lock.is_dirty() {
+                self.lock.save(lock_path)?;
+                saved.lock_file = Some(lock_path.display().to_string());
+            }
+        }
+
+        Ok(saved)
+    }
+}
+
+/// Back up the files of a directory that are about to change when `backups` is set,
+/// then write the modified tables back to them
+pub fn save_with_backup(
+    directory: &Path,
+    nucc_binaries: &mut NuccBinaries,
+    backups: bool,
+) -> Result<SaveResult> {
+    let files = dirty_files(nucc_binaries);
+
+    if files.is_empty() {
+        return Ok(SaveResult::default());
+    }
+
+    let mut backup = if backups {
+        Some(BackupManifest::create(directory, &files)?)
+    } else {
+        None
+    };
+
+    let written_files = match save_nucc_binaries(nucc_binaries) {
+        Ok(written_files) => written_files,
+        Err(e) => {
+            // The originals are untouched, so the snapshot isn't needed
+            if let Some(backup) = &backup {
+                backup.discard(directory);
+            }
+            return Err(e);
+        }
+    };
+
+    if let Some(backup) = &mut backup {
+        backup.record_written(directory)?;
+    }
+
+    Ok(SaveResult {
+        written_files,
+        backup,
+        lock_file: None,
+    })
+}
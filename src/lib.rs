@@ -0,0 +1,14 @@
+//! Adds costume entries to the Ultimate Ninja Storm CONNECTIONS param files.
+//!
+//! The [`installer::CostumeInstaller`] loads the param tables from a data_win32 directory,
+//! applies a [`cfg::CostumeAddConfig`] to them and writes the modified files back.
+
+pub mod backup;
+pub mod build;
+pub mod cfg;
+pub mod error;
+pub mod installer;
+pub mod lock;
+pub mod merge;
+pub mod param;
+pub mod validate;
@@ -0,0 +1,171 @@
+use crate::error::{CosprmError, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The lockfile that sits next to the costume config
+pub const LOCK_FILE: &str = "cosprm.lock";
+
+/// The ids cosprm assigned to each costume, so reinstalling or installing mods
+/// in a different order gives every costume the same ids again
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct IdLock {
+    /// Keyed by the costume's `lock_key`, so costumes that share a cha_id keep their own ids
+    pub costumes: BTreeMap<String, LockedIds>,
+    /// The model indexes and message ids picked for costumes that left them unset,
+    /// keyed by "characode/modelcode"
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub assigned: BTreeMap<String, AssignedIds>,
+    #[serde(skip)]
+    dirty: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LockedIds {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub player_setting_id: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub searchcode: Option<String>,
+    /// One costume_link per color, in color_index order
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub costume_links: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AssignedIds {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model_index: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cha_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub costume_id: Option<String>,
+}
+
+impl IdLock {
+    /// Where the lockfile of a config, or of a directory of configs, lives
+    pub fn path_for(config: impl AsRef<Path>) -> PathBuf {
+        if config.as_ref().is_dir() {
+            return config.as_ref().join(LOCK_FILE);
+        }
+
+        match config.as_ref().parent() {
+            Some(parent) => parent.join(LOCK_FILE),
+            None => PathBuf::from(LOCK_FILE),
+        }
+    }
+
+    /// Read a lockfile, or start an empty one if it doesn't exist yet
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let json_str = fs::read_to_string(path).map_err(|source| CosprmError::ConfigRead {
+            path: path.display().to_string(),
+            source,
+        })?;
+
+        serde_json::from_str(&json_str).map_err(|source| CosprmError::ConfigParse {
+            path: path.display().to_string(),
+            source,
+        })
+    }
+
+    pub fn save(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+
+        let json_str = serde_json::to_string_pretty(self)
+            .map_err(|source| CosprmError::json_write(path, source))?;
+
+        fs::write(path, json_str).map_err(|source| CosprmError::io(path, source))?;
+
+        self.dirty = false;
+
+        Ok(())
+    }
+
+    /// Whether ids were recorded since the lockfile was loaded or saved
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    pub fn get(&self, key: &str) -> Option<&LockedIds> {
+        self.costumes.get(key)
+    }
+
+    pub fn get_assigned(&self, key: &str) -> Option<&AssignedIds> {
+        self.assigned.get(key)
+    }
+
+    /// Model indexes and message ids that are promised to costumes other than `key`,
+    /// along with the key of the costume they belong to
+    pub fn reserved_assigned<'a>(
+        &'a self,
+        key: &'a str,
+    ) -> impl Iterator<Item = (&'a str, &'a AssignedIds)> + 'a {
+        self.assigned
+            .iter()
+            .filter(move |(assigned_key, _)| assigned_key.as_str() != key)
+            .map(|(assigned_key, ids)| (assigned_key.as_str(), ids))
+    }
+
+    pub fn record_assigned(&mut self, key: &str, assigned: AssignedIds) {
+        let ids = self.assigned.entry(key.to_string()).or_default();
+
+        if *ids != assigned {
+            *ids = assigned;
+            self.dirty = true;
+        }
+    }
+
+    /// Ids that are promised to costumes other than `key`, so they aren't handed out twice
+    pub fn reserved_player_setting_ids(&self, key: &str) -> Vec<u32> {
+        self.others(key)
+            .filter_map(|ids| ids.player_setting_id)
+            .collect()
+    }
+
+    pub fn reserved_searchcodes(&self, key: &str) -> Vec<&str> {
+        self.costumes
+            .iter()
+            .filter(|(locked_key, _)| locked_key.as_str() != key)
+            .filter_map(|(_, ids)| ids.searchcode.as_deref())
+            .collect()
+    }
+
+    pub fn record_player_setting(&mut self, key: &str, player_setting_id: u32, searchcode: &str) {
+        let ids = self.costumes.entry(key.to_string()).or_default();
+
+        if ids.player_setting_id != Some(player_setting_id)
+            || ids.searchcode.as_deref() != Some(searchcode)
+        {
+            ids.player_setting_id = Some(player_setting_id);
+            ids.searchcode = Some(searchcode.to_string());
+            self.dirty = true;
+        }
+    }
+
+    pub fn record_costume_link(&mut self, key: &str, color_index: u32, costume_link: &str) {
+        let ids = self.costumes.entry(key.to_string()).or_default();
+        let color_index = color_index as usize;
+
+        if ids.costume_links.len() <= color_index {
+            ids.costume_links.resize(color_index + 1, String::new());
+        }
+
+        if ids.costume_links[color_index] != costume_link {
+            ids.costume_links[color_index] = costume_link.to_string();
+            self.dirty = true;
+        }
+    }
+
+    fn others<'a>(&'a self, key: &'a str) -> impl Iterator<Item = &'a LockedIds> + 'a {
+        self.costumes
+            .iter()
+            .filter(move |(locked_key, _)| locked_key.as_str() != key)
+            .map(|(_, ids)| ids)
+    }
+}
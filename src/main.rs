@@ -1,11 +1,20 @@
-mod cfg;
-mod param;
-
-use cfg::CostumeAddConfig;
-use clap::Parser;
-use nuccbin::NuccBinaryType;
-use param::{add_entry::*, nucc_binary_handler::*};
-use std::path::Path;
+use clap::{Parser, Subcommand};
+use cosprm::backup::BackupManifest;
+use cosprm::build::copy_vanilla_files;
+use cosprm::cfg::{CostumeAddConfig, CostumeBreakConfig, CostumeConfig};
+use cosprm::error::{CosprmError, Result};
+use cosprm::installer::{save_with_backup, CostumeInstaller, InstallResult};
+use cosprm::lock::IdLock;
+use cosprm::merge::{merge_configs, read_sources, MergedConfig};
+use cosprm::param::check::{check_integrity, Severity};
+use cosprm::param::extract::{extract_costume, CostumeSelector};
+use cosprm::param::list::{list_costumes, list_messages, print_listing, print_messages};
+use cosprm::param::message_dictionary::MessageDictionary;
+use cosprm::param::nucc_binary_handler::{get_nucc_binaries, NuccBinaries};
+use cosprm::param::table_file::{export_tables, import_tables, TableFormat};
+use cosprm::validate::validate_config;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::{thread, time};
 
 #[derive(Parser, Debug)]
@@ -16,78 +25,783 @@ use std::{thread, time};
     about = "A tool to add costume entries to NSC param files."
 )]
 struct Args {
-    #[clap(short, long)]
-    json: String,
-    #[clap(short, long)]
-    dir: String,
+    #[clap(subcommand)]
+    command: Option<Command>,
+    /// A costume config, or a directory of them. Can be passed several times,
+    /// later configs have a higher priority
+    #[clap(short, long, global = true)]
+    json: Vec<String>,
+    #[clap(short, long, global = true)]
+    dir: Option<String>,
+    /// Print the entries that would be changed without writing any files
+    #[clap(long, global = true)]
+    dry_run: bool,
+    /// Print the report of changed entries as JSON, with progress messages on stderr
+    #[clap(long, global = true)]
+    report_json: bool,
+    /// Update costumes that were already added to match the config instead of skipping them
+    #[clap(long, global = true)]
+    update: bool,
+    /// Don't back up the param files before modifying them
+    #[clap(long, global = true)]
+    no_backup: bool,
+    /// Resolve conflicts between configs by keeping the costume of the config passed last
+    #[clap(long, global = true)]
+    resolve_by_priority: bool,
+    /// Write the model indexes and ids that were picked for costumes back into their configs
+    #[clap(long, global = true)]
+    write_back: bool,
+    /// A text file of message ids, one per line, to recognize MessageInfo crc32s by.
+    /// Can be passed several times
+    #[clap(long, global = true)]
+    message_ids: Vec<String>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Add a single costume from command line flags instead of a .json
+    Add(QuickAddArgs),
+    /// Remove the entries that were added for the costumes in the config
+    Remove,
+    /// Check the config for problems without changing any files.
+    /// Also checks it against the param tables when --dir is passed
+    Validate,
+    /// List the backups of the directory, or restore one of them
+    Restore {
+        /// The snapshot to restore, lists every snapshot when omitted
+        #[clap(short, long)]
+        snapshot: Option<String>,
+        /// Restore even if the files were changed since the backup was made
+        #[clap(long)]
+        force: bool,
+    },
+    /// List the costumes that are already in the param tables, per character.
+    /// Prints JSON with --report-json
+    List {
+        /// Only list the costumes of this characode
+        #[clap(long)]
+        characode: Option<String>,
+        /// The MessageInfo language names are shown in
+        #[clap(long)]
+        language: Option<String>,
+        /// List the MessageInfo rows with the ids of their crc32 instead of the costumes
+        #[clap(long)]
+        messages: bool,
+    },
+    /// Check that the rows of the param tables reference each other correctly.
+    /// Fails when errors are found
+    Check {
+        /// Fail on warnings too
+        #[clap(long)]
+        strict: bool,
+    },
+    /// Write the config of a costume that's already in the param tables, to add it elsewhere.
+    /// Pick it with --searchcode, or with --characode and --model-index
+    Extract {
+        #[clap(long)]
+        searchcode: Option<String>,
+        #[clap(long)]
+        characode: Option<String>,
+        #[clap(long)]
+        model_index: Option<i32>,
+        /// The modelcode of the costume, which isn't stored in the param tables
+        #[clap(long)]
+        modelcode: Option<String>,
+        /// The MessageInfo language the names are taken from
+        #[clap(long)]
+        language: Option<String>,
+        /// Write the config to this file instead of printing it
+        #[clap(short, long)]
+        out: Option<String>,
+    },
+    /// Export the param tables to one JSON or CSV file per table
+    Export {
+        /// The directory the table files are written to
+        #[clap(short, long)]
+        out: String,
+        /// json or csv
+        #[clap(long, default_value = "json")]
+        format: TableFormat,
+    },
+    /// Import edited table files that were exported before back into the param files
+    Import {
+        /// The directory holding the table files
+        #[clap(short, long)]
+        input: String,
+    },
+    /// Build the param files from a pristine copy of the vanilla files and every config in a
+    /// directory, so the output only depends on the configs that are present
+    Build {
+        /// The directory holding the untouched vanilla param files
+        #[clap(long)]
+        vanilla: String,
+        /// The directory holding the costume configs (.json) to apply
+        #[clap(long)]
+        configs: String,
+        /// The directory the param files are written to
+        #[clap(short, long)]
+        out: String,
+    },
+}
+
+#[derive(clap::Args, Debug)]
+struct QuickAddArgs {
+    #[clap(long)]
+    characode: String,
+    #[clap(long)]
+    modelcode: String,
+    /// Defaults to the next unused model index of the character
+    #[clap(long)]
+    model_index: Option<i32>,
+    /// The icon of the costume, the character's icon is kept when omitted
+    #[clap(long)]
+    iconcode: Option<String>,
+    /// Defaults to the next unused c_cha_NNN
+    #[clap(long)]
+    cha_id: Option<String>,
+    /// Defaults to the characode
+    #[clap(long)]
+    char_name: Option<String>,
+    /// Defaults to the next unused c_costume_NNN
+    #[clap(long)]
+    costume_id: Option<String>,
+    /// Defaults to the character name
+    #[clap(long)]
+    costume_name: Option<String>,
+    /// The number of colors
+    #[clap(long, default_value = "1")]
+    colors: i32,
+    /// Whether the costume has a costume break
+    #[clap(long)]
+    costume_break: bool,
+    /// The modelcode of the torn model, implies --costume-break
+    #[clap(long)]
+    broken_modelcode: Option<String>,
+    #[clap(long)]
+    price: Option<u32>,
+    #[clap(long)]
+    unlock_condition: Option<u32>,
+    /// Add the costume to this config once it was installed, creating the file if needed
+    #[clap(long)]
+    append_to: Option<String>,
 }
 
 fn main() {
     let args = Args::parse();
 
-    let cfg = CostumeAddConfig::read_cfg(args.json.as_str());
+    let result = match &args.command {
+        None => add_costumes(&args),
+        Some(Command::Add(add)) => quick_add_costume(&args, add),
+        Some(Command::Remove) => remove_costumes(&args),
+        Some(Command::Validate) => validate_costumes(&args),
+        Some(Command::Restore { snapshot, force }) => {
+            restore_backup(&args, snapshot.as_deref(), *force)
+        }
+        Some(Command::List {
+            characode,
+            language,
+            messages,
+        }) => list_existing_costumes(&args, characode.as_deref(), language.as_deref(), *messages),
+        Some(Command::Check { strict }) => check_param_tables(&args, *strict),
+        Some(Command::Extract {
+            searchcode,
+            characode,
+            model_index,
+            modelcode,
+            language,
+            out,
+        }) => extract_existing_costume(
+            &args,
+            searchcode,
+            characode,
+            *model_index,
+            modelcode,
+            language.as_deref(),
+            out.as_deref(),
+        ),
+        Some(Command::Export { out, format }) => export_param_tables(&args, out, *format),
+        Some(Command::Import { input }) => import_param_tables(&args, input),
+        Some(Command::Build {
+            vanilla,
+            configs,
+            out,
+        }) => build_costumes(&args, vanilla, configs, out),
+    };
+
+    if let Err(e) = result {
+        eprintln!("error: {}", e);
+        std::process::exit(e.exit_code());
+    }
+
+    // The pause keeps the window open when run by double clicking, tools reading the JSON don't need it.
+    // The banner goes to stderr so it doesn't end up in output that's piped, e.g. the config of extract
+    if !args.report_json {
+        eprintln!("Exiting...");
+        thread::sleep(time::Duration::from_secs(2));
+    }
+}
 
-    let directory = Path::new(args.dir.as_str());
+fn add_costumes(args: &Args) -> Result<()> {
+    let merged = load_configs(args, &args.json)?;
 
-    let mut nucc_binaries = get_nucc_binaries(&directory);
+    // The lockfile is shared by the configs and kept next to the first one
+    let installed = install_costumes(args, merged.cfg, Some(IdLock::path_for(&args.json[0])))?;
 
-    // Check if each required NUCC binary type is present in the directory
-    let required_nucc_types = vec![
-        NuccBinaryType::MessageInfo,
-        NuccBinaryType::PlayerSettingParam,
-        NuccBinaryType::CostumeParam,
-        NuccBinaryType::PlayerIcon,
-        NuccBinaryType::CharacterSelectParam,
-        NuccBinaryType::CostumeBreakParam,
-    ];
+    if let (Some(installed), true) = (installed, args.write_back) {
+        for file in &merged.files {
+            let mut cfg = CostumeAddConfig::read_cfg(file)?;
 
-    for nucc_type in &required_nucc_types {
-        if !nucc_binaries.contains_key(nucc_type) {
-            // Handle the case when the NUCC binary type is missing
-            println!(
+            if cfg.fill_assigned_ids(&installed) {
+                cfg.write_cfg(file)?;
+                status(args, format!("Wrote the assigned ids to {}", file));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn quick_add_costume(args: &Args, add: &QuickAddArgs) -> Result<()> {
+    let char_name = add
+        .char_name
+        .clone()
+        .unwrap_or_else(|| add.characode.clone());
+
+    let costume = CostumeConfig {
+        model_index: add.model_index.unwrap_or_default(),
+        characode: add.characode.clone(),
+        modelcode: add.modelcode.clone(),
+        iconcode: add.iconcode.clone().unwrap_or_default(),
+        cha_id: add.cha_id.clone().unwrap_or_default(),
+        char_name: char_name.clone(),
+        costume_id: add.costume_id.clone().unwrap_or_default(),
+        costume_name: add.costume_name.clone().unwrap_or(char_name),
+        color_count: add.colors,
+        has_costume_break: add.costume_break,
+        costume_break: add
+            .broken_modelcode
+            .as_ref()
+            .map(|broken_modelcode| CostumeBreakConfig {
+                broken_modelcode: Some(broken_modelcode.clone()),
+                ..Default::default()
+            }),
+        names: HashMap::new(),
+        price: add.price,
+        unlock_condition: add.unlock_condition,
+        colors: Vec::new(),
+        template: None,
+    };
+
+    let cfg = CostumeAddConfig {
+        costumes: vec![costume],
+    };
+
+    let lock_path = add.append_to.as_ref().map(IdLock::path_for);
+
+    let installed = match install_costumes(args, cfg, lock_path)? {
+        Some(installed) => installed,
+        None => return Ok(()),
+    };
+
+    if let Some(append_to) = &add.append_to {
+        let mut appended = if Path::new(append_to).exists() {
+            CostumeAddConfig::read_cfg(append_to)?
+        } else {
+            CostumeAddConfig {
+                costumes: Vec::new(),
+            }
+        };
+
+        for costume in installed.costumes {
+            // Replace the costume if the config already has one in the same slot
+            appended.costumes.retain(|existing| {
+                existing.characode != costume.characode
+                    || existing.model_index != costume.model_index
+            });
+            appended.costumes.push(costume);
+        }
+
+        appended.write_cfg(append_to)?;
+        status(args, format!("Appended the costume to {}", append_to));
+    }
+
+    Ok(())
+}
+
+/// Install the costumes of a config and save them unless this is a dry run.
+/// Returns the config with the ids that were assigned, or None on a dry run
+fn install_costumes(
+    args: &Args,
+    cfg: CostumeAddConfig,
+    lock_path: Option<PathBuf>,
+) -> Result<Option<CostumeAddConfig>> {
+    let mut installer = CostumeInstaller::new(required_arg(&args.dir, "dir")?, cfg)?;
+    installer.set_backups(!args.no_backup);
+
+    if let Some(lock_path) = lock_path {
+        installer.set_lock_file(lock_path)?;
+    }
+
+    load_message_ids(args, &mut installer)?;
+
+    installer.set_update(args.update);
+
+    let result = installer.install()?;
+
+    if !finish(args, &mut installer, &result, "added")? {
+        return Ok(None);
+    }
+
+    for costume in &installer.config().costumes {
+        status(
+            args,
+            format!(
+                "Added costume {}bod1 for {}",
+                costume.modelcode, costume.characode
+            ),
+        );
+    }
+    status(args, "Costume entries added successfully...".to_string());
+
+    Ok(Some(installer.config().clone()))
+}
+
+fn remove_costumes(args: &Args) -> Result<()> {
+    let cfg = load_configs(args, &args.json)?.cfg;
+
+    let mut installer = CostumeInstaller::new(required_arg(&args.dir, "dir")?, cfg)?;
+    installer.set_backups(!args.no_backup);
+    // Costumes that left their ids unset are found through the lockfile
+    installer.set_lock_file(IdLock::path_for(&args.json[0]))?;
+
+    let result = installer.uninstall()?;
+
+    for costume in &result.skipped {
+        status(
+            args,
+            format!(
+                "Skipped {}, its model index or ids are unset and the lockfile doesn't record them",
+                installer.config().costumes[*costume].label()
+            ),
+        );
+    }
+
+    if !finish(args, &mut installer, &result, "removed")? {
+        return Ok(());
+    }
+
+    status(args, "Costume entries removed successfully...".to_string());
+
+    Ok(())
+}
+
+fn validate_costumes(args: &Args) -> Result<()> {
+    let cfg = load_configs(args, &args.json)?.cfg;
+
+    let issues = match &args.dir {
+        Some(dir) => {
+            let mut installer = CostumeInstaller::new(dir, cfg)?;
+            // Ids of costumes that were installed before are recorded in the lockfile
+            installer.set_lock_file(IdLock::path_for(&args.json[0]))?;
+            load_message_ids(args, &mut installer)?;
+            installer.validate()
+        }
+        None => validate_config(&cfg),
+    };
+
+    if !issues.is_empty() {
+        return Err(CosprmError::InvalidConfig { issues });
+    }
+
+    println!("The config is valid.");
+
+    Ok(())
+}
+
+fn list_existing_costumes(
+    args: &Args,
+    characode: Option<&str>,
+    language: Option<&str>,
+    messages: bool,
+) -> Result<()> {
+    let directory = Path::new(required_arg(&args.dir, "dir")?);
+    let nucc_binaries = get_nucc_binaries(directory)?;
+
+    if messages {
+        let message_ids = message_dictionary(args, &nucc_binaries)?;
+        let messages = list_messages(&nucc_binaries, &message_ids, language);
+
+        if args.report_json {
+            let json_str = serde_json::to_string_pretty(&messages)
+                .map_err(|source| CosprmError::json_write("the message listing", source))?;
+            println!("{}", json_str);
+        } else {
+            print_messages(&messages);
+        }
+
+        return Ok(());
+    }
+
+    let characters = list_costumes(&nucc_binaries, characode, language)?;
+
+    if args.report_json {
+        let json_str = serde_json::to_string_pretty(&characters)
+            .map_err(|source| CosprmError::json_write("the costume listing", source))?;
+        println!("{}", json_str);
+    } else {
+        print_listing(&characters);
+    }
+
+    Ok(())
+}
+
+fn check_param_tables(args: &Args, strict: bool) -> Result<()> {
+    let nucc_binaries = get_nucc_binaries(Path::new(required_arg(&args.dir, "dir")?))?;
+
+    let issues = check_integrity(&nucc_binaries)?;
+
+    if args.report_json {
+        let json_str = serde_json::to_string_pretty(&issues)
+            .map_err(|source| CosprmError::json_write("the integrity report", source))?;
+        println!("{}", json_str);
+    } else if issues.is_empty() {
+        println!("No problems found.");
+    } else {
+        for issue in &issues {
+            println!("{}", issue);
+        }
+    }
+
+    let count = |severity| {
+        issues
+            .iter()
+            .filter(|issue| issue.severity == severity)
+            .count()
+    };
+    let errors = count(Severity::Error);
+    let warnings = count(Severity::Warning);
+
+    if errors > 0 || (strict && warnings > 0) {
+        return Err(CosprmError::IntegrityCheckFailed { errors, warnings });
+    }
+
+    Ok(())
+}
+
+fn extract_existing_costume(
+    args: &Args,
+    searchcode: &Option<String>,
+    characode: &Option<String>,
+    model_index: Option<i32>,
+    modelcode: &Option<String>,
+    language: Option<&str>,
+    out: Option<&str>,
+) -> Result<()> {
+    let selector = match (searchcode, characode, model_index) {
+        (Some(searchcode), _, _) => CostumeSelector::Searchcode(searchcode.clone()),
+        (None, Some(characode), Some(model_index)) => CostumeSelector::Model {
+            characode: characode.clone(),
+            model_index,
+        },
+        (None, Some(_), None) => {
+            return Err(CosprmError::MissingArgument {
+                name: "model-index".to_string(),
+            })
+        }
+        _ => {
+            return Err(CosprmError::MissingArgument {
+                name: "searchcode".to_string(),
+            })
+        }
+    };
+
+    let nucc_binaries = get_nucc_binaries(Path::new(required_arg(&args.dir, "dir")?))?;
+
+    let mut costume = extract_costume(&nucc_binaries, &selector, language)?;
+
+    // An empty modelcode fails validation, so the config can't install the wrong model
+    costume.modelcode = match modelcode {
+        Some(modelcode) => modelcode.clone(),
+        None => {
+            eprintln!(
+                "The modelcode isn't stored in the param tables, fill it in or pass --modelcode."
+            );
+            String::new()
+        }
+    };
+
+    let cfg = CostumeAddConfig {
+        costumes: vec![costume],
+    };
+
+    let json_str = serde_json::to_string_pretty(&cfg)
+        .map_err(|source| CosprmError::json_write(out.unwrap_or("the config"), source))?;
+
+    match out {
+        Some(out) => {
+            std::fs::write(out, json_str).map_err(|source| CosprmError::io(out, source))?;
+            println!("Wrote the config of {} to {}", selector, out);
+        }
+        None => println!("{}", json_str),
+    }
+
+    Ok(())
+}
+
+fn export_param_tables(args: &Args, out: &str, format: TableFormat) -> Result<()> {
+    let nucc_binaries = get_nucc_binaries(Path::new(required_arg(&args.dir, "dir")?))?;
+    let message_ids = message_dictionary(args, &nucc_binaries)?;
+
+    for file in export_tables(&nucc_binaries, Path::new(out), format, &message_ids)? {
+        println!("Exported {}", file);
+    }
+
+    Ok(())
+}
+
+fn import_param_tables(args: &Args, input: &str) -> Result<()> {
+    let directory = Path::new(required_arg(&args.dir, "dir")?);
+    let mut nucc_binaries = get_nucc_binaries(directory)?;
+
+    let imported = import_tables(&mut nucc_binaries, Path::new(input))?;
+
+    if imported.is_empty() {
+        println!("No tables were changed.");
+        return Ok(());
+    }
+
+    for file in &imported {
+        println!("Imported {}", file);
+    }
+
+    if args.dry_run {
+        println!("Dry run, no files were written.");
+        return Ok(());
+    }
+
+    let saved = save_with_backup(directory, &mut nucc_binaries, !args.no_backup)?;
+
+    if let Some(backup) = &saved.backup {
+        println!("Backed up original files to snapshot {}", backup.snapshot);
+    }
+
+    for file in &saved.written_files {
+        println!("Wrote {}", file);
+    }
+
+    Ok(())
+}
+
+fn build_costumes(args: &Args, vanilla: &str, configs: &str, out: &str) -> Result<()> {
+    let merged = load_configs(args, &[configs.to_string()])?;
+
+    for file in &merged.files {
+        status(args, format!("Using config {}", file));
+    }
+
+    // A dry run applies the configs to the vanilla tables without writing anything
+    let directory = if args.dry_run {
+        vanilla
+    } else {
+        for file in copy_vanilla_files(Path::new(vanilla), Path::new(out))? {
+            status(args, format!("Copied vanilla {}", file));
+        }
+        out
+    };
+
+    let config_count = merged.files.len();
+
+    let mut installer = CostumeInstaller::new(directory, merged.cfg)?;
+    // The output is rebuilt from scratch, so there's nothing to back up
+    installer.set_backups(false);
+    installer.set_lock_file(IdLock::path_for(configs))?;
+    load_message_ids(args, &mut installer)?;
+
+    let result = installer.install()?;
+
+    if !finish(args, &mut installer, &result, "added")? {
+        return Ok(());
+    }
+
+    status(
+        args,
+        format!(
+            "Built {} costume(s) from {} config(s) into {}",
+            installer.config().costumes.len(),
+            config_count,
+            out
+        ),
+    );
+
+    Ok(())
+}
+
+/// The known message ids of the tables along with the ones of every --message-ids file
+fn message_dictionary(args: &Args, nucc_binaries: &NuccBinaries) -> Result<MessageDictionary> {
+    let mut message_ids = MessageDictionary::for_tables(nucc_binaries);
+
+    for file in &args.message_ids {
+        message_ids.load_file(file)?;
+    }
+
+    Ok(message_ids)
+}
+
+fn load_message_ids(args: &Args, installer: &mut CostumeInstaller) -> Result<()> {
+    for file in &args.message_ids {
+        installer.load_message_ids(file)?;
+    }
+
+    Ok(())
+}
+
+/// Print the report and save the binaries unless this is a dry run.
+/// Returns whether the files were written
+fn finish(
+    args: &Args,
+    installer: &mut CostumeInstaller,
+    result: &InstallResult,
+    action: &str,
+) -> Result<bool> {
+    let report = &result.report;
+
+    for assigned in &result.assigned {
+        status(
+            args,
+            format!(
+                "Assigned {} {} to {}",
+                assigned.field,
+                assigned.value,
+                installer.config().costumes[assigned.costume].label()
+            ),
+        );
+    }
+
+    for nucc_type in &result.missing_nucc_types {
+        status(
+            args,
+            format!(
                 "NUCC binary type {:?} is missing from the directory.",
                 nucc_type
-            );
+            ),
+        );
+    }
+
+    if args.dry_run {
+        if args.report_json {
+            println!("{}", report.to_json()?);
         } else {
-            match nucc_type {
-                NuccBinaryType::MessageInfo => {
-                    add_message_info_entry(&mut nucc_binaries, &cfg);
-                }
+            println!(
+                "Dry run, no files were written. Entries that would be {}:",
+                action
+            );
+            report.print();
+        }
+        return Ok(false);
+    }
 
-                NuccBinaryType::PlayerSettingParam => {
-                    add_player_setting_entry(&mut nucc_binaries, &cfg);
-                }
+    let saved = installer.save()?;
 
-                NuccBinaryType::CostumeParam => {
-                    add_costume_entry(&mut nucc_binaries, &cfg);
-                }
+    if args.report_json {
+        println!("{}", report.to_json()?);
+    }
 
-                NuccBinaryType::PlayerIcon => {
-                    add_icon_entry(&mut nucc_binaries, &cfg);
-                }
+    if let Some(backup) = &saved.backup {
+        status(
+            args,
+            format!("Backed up original files to snapshot {}", backup.snapshot),
+        );
+    }
 
-                NuccBinaryType::CharacterSelectParam => {
-                    add_character_select_entry(&mut nucc_binaries, &cfg);
-                }
+    for file in &saved.written_files {
+        status(args, format!("Wrote {}", file));
+    }
 
-                NuccBinaryType::CostumeBreakParam => {
-                    add_costume_break_entry(&mut nucc_binaries, &cfg);
-                }
+    if let Some(lock_file) = &saved.lock_file {
+        status(args, format!("Recorded the allocated ids in {}", lock_file));
+    }
+
+    Ok(true)
+}
 
-                _ => {}
+/// Print a progress line, on stderr when stdout is taken by the JSON report
+fn status(args: &Args, line: String) {
+    if args.report_json {
+        eprintln!("{}", line);
+    } else {
+        println!("{}", line);
+    }
+}
+
+fn restore_backup(args: &Args, snapshot: Option<&str>, force: bool) -> Result<()> {
+    let directory = Path::new(required_arg(&args.dir, "dir")?);
+
+    let snapshot = match snapshot {
+        Some(snapshot) => snapshot,
+        None => {
+            let backups = BackupManifest::list(directory)?;
+
+            if backups.is_empty() {
+                println!("No backups found in {}", directory.display());
             }
+
+            for backup in &backups {
+                println!(
+                    "{} ({} files, unix time {})",
+                    backup.snapshot,
+                    backup.files.len(),
+                    backup.timestamp
+                );
+                for file in &backup.files {
+                    println!("    {}", file.path);
+                }
+            }
+            return Ok(());
         }
+    };
+
+    let backup = BackupManifest::load(directory, snapshot)?;
+
+    for file in backup.restore(directory, force)? {
+        println!("Restored {}", file);
     }
+    println!("Snapshot {} restored successfully...", backup.snapshot);
 
-    save_nucc_binaries(&directory, &mut nucc_binaries);
+    Ok(())
+}
 
-    for costume in &cfg.costumes {
-        println!(
-            "Added costume {}bod1 for {}",
-            costume.modelcode, costume.characode
+/// Read and merge the configs, printing the conflicts that were resolved by priority
+fn load_configs(args: &Args, paths: &[String]) -> Result<MergedConfig> {
+    if paths.is_empty() {
+        return Err(CosprmError::MissingArgument {
+            name: "json".to_string(),
+        });
+    }
+
+    let merged = merge_configs(read_sources(paths)?, args.resolve_by_priority)?;
+
+    for conflict in &merged.resolved {
+        status(
+            args,
+            format!(
+                "Conflict resolved in favor of {}: {}",
+                conflict
+                    .files
+                    .last()
+                    .map(String::as_str)
+                    .unwrap_or_default(),
+                conflict
+            ),
         );
     }
-    println!("Costume entries added successfully...");
-    println!("Exiting...");
-    thread::sleep(time::Duration::from_secs(2));
+
+    Ok(merged)
+}
+
+fn required_arg<'a>(value: &'a Option<String>, name: &str) -> Result<&'a str> {
+    value
+        .as_deref()
+        .ok_or_else(|| CosprmError::MissingArgument {
+            name: name.to_string(),
+        })
 }
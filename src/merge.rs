@@ -0,0 +1,295 @@
+use crate::cfg::{CostumeAddConfig, CostumeConfig};
+use crate::error::{CosprmError, Result};
+use serde::Serialize;
+use std::fmt;
+use std::path::Path;
+
+/// A config along with the file it was read from
+#[derive(Debug, Clone)]
+pub struct ConfigSource {
+    pub path: String,
+    pub cfg: CostumeAddConfig,
+}
+
+/// Two configs that claim the same thing
+#[derive(Debug, Clone, Serialize)]
+pub struct ConfigConflict {
+    pub kind: ConflictKind,
+    pub description: String,
+    /// The files involved, from lowest to highest priority
+    pub files: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictKind {
+    /// The same characode and model_index, which is also the character select slot
+    ModelIndex,
+    /// The same cha_id with different character names
+    ChaIdName,
+    /// The same costume_id
+    CostumeId,
+}
+
+impl fmt::Display for ConfigConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.description, self.files.join(", "))
+    }
+}
+
+/// The costumes of every config merged into one
+#[derive(Debug, Clone)]
+pub struct MergedConfig {
+    pub cfg: CostumeAddConfig,
+    /// Every file that was read, in priority order
+    pub files: Vec<String>,
+    /// Conflicts that were resolved by dropping the costume of the lower priority file
+    pub resolved: Vec<ConfigConflict>,
+}
+
+/// Read the configs at the paths. Directories are expanded to the .json files in them,
+/// in file name order
+pub fn read_sources(paths: &[String]) -> Result<Vec<ConfigSource>> {
+    let mut sources = Vec::new();
+
+    for path in paths {
+        let files = if Path::new(path).is_dir() {
+            CostumeAddConfig::config_files(Path::new(path))?
+        } else {
+            vec![path.clone()]
+        };
+
+        for file in files {
+            let cfg = CostumeAddConfig::read_cfg(&file)?;
+            sources.push(ConfigSource { path: file, cfg });
+        }
+    }
+
+    Ok(sources)
+}
+
+/// Merge the costumes of every source, checking that no two files claim the same model index,
+/// cha_id or costume_id.
+///
+/// Later sources have a higher priority. When `resolve_by_priority` is set a conflicting costume
+/// of a lower priority file is dropped, otherwise every conflict is returned as an error
+pub fn merge_configs(
+    sources: Vec<ConfigSource>,
+    resolve_by_priority: bool,
+) -> Result<MergedConfig> {
+    // Every costume remembers the index of the file it came from
+    let mut costumes: Vec<(usize, CostumeConfig)> = Vec::new();
+    let mut conflicts = Vec::new();
+    let mut resolved = Vec::new();
+
+    let files = sources
+        .iter()
+        .map(|source| source.path.clone())
+        .collect::<Vec<_>>();
+
+    for (source_index, source) in sources.into_iter().enumerate() {
+        for costume in source.cfg.costumes {
+            let mut dropped = Vec::new();
+
+            // Costumes of the same file are checked by validation instead
+            for (i, (other_index, other)) in costumes.iter().enumerate() {
+                if *other_index == source_index {
+                    continue;
+                }
+
+                for (kind, description) in find_conflicts(other, &costume) {
+                    let conflict = ConfigConflict {
+                        kind,
+                        description,
+                        files: vec![files[*other_index].clone(), files[source_index].clone()],
+                    };
+
+                    if resolve_by_priority {
+                        resolved.push(conflict);
+                        dropped.push(i);
+                    } else {
+                        conflicts.push(conflict);
+                    }
+                }
+            }
+
+            dropped.dedup();
+            for i in dropped.into_iter().rev() {
+                costumes.remove(i);
+            }
+
+            costumes.push((source_index, costume));
+        }
+    }
+
+    if !conflicts.is_empty() {
+        return Err(CosprmError::ConfigConflict { conflicts });
+    }
+
+    Ok(MergedConfig {
+        cfg: CostumeAddConfig {
+            costumes: costumes.into_iter().map(|(_, costume)| costume).collect(),
+        },
+        files,
+        resolved,
+    })
+}
+
+fn find_conflicts(a: &CostumeConfig, b: &CostumeConfig) -> Vec<(ConflictKind, String)> {
+    let mut conflicts = Vec::new();
+
+    // Model indexes and ids that are left unset are picked so they don't collide
+    if a.model_index != 0 && a.characode == b.characode && a.model_index == b.model_index {
+        conflicts.push((
+            ConflictKind::ModelIndex,
+            format!(
+                "{} and {} both use model index {} (character select slot) of {}",
+                a.cha_id, b.cha_id, a.model_index, a.characode
+            ),
+        ));
+    }
+
+    if !a.cha_id.is_empty() && a.cha_id == b.cha_id && !same_char_names(a, b) {
+        conflicts.push((
+            ConflictKind::ChaIdName,
+            format!(
+                "{} is named both \"{}\" and \"{}\"",
+                a.cha_id, a.char_name, b.char_name
+            ),
+        ));
+    }
+
+    if !a.costume_id.is_empty() && a.costume_id == b.costume_id {
+        conflicts.push((
+            ConflictKind::CostumeId,
+            format!("{} is used by two costumes", a.costume_id),
+        ));
+    }
+
+    conflicts
+}
+
+fn same_char_names(a: &CostumeConfig, b: &CostumeConfig) -> bool {
+    a.char_name == b.char_name
+        && a.names.keys().chain(b.names.keys()).all(|language| {
+            a.char_name_for(Some(language.as_str())) == b.char_name_for(Some(language.as_str()))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn costume(model_index: i32, cha_id: &str, char_name: &str, costume_id: &str) -> CostumeConfig {
+        CostumeConfig {
+            model_index,
+            characode: "3mnt".to_string(),
+            modelcode: "3mnc".to_string(),
+            iconcode: String::new(),
+            cha_id: cha_id.to_string(),
+            char_name: char_name.to_string(),
+            costume_id: costume_id.to_string(),
+            costume_name: "Costume".to_string(),
+            color_count: 1,
+            has_costume_break: false,
+            costume_break: None,
+            names: Default::default(),
+            price: None,
+            unlock_condition: None,
+            colors: Vec::new(),
+            template: None,
+        }
+    }
+
+    fn source(path: &str, costumes: Vec<CostumeConfig>) -> ConfigSource {
+        ConfigSource {
+            path: path.to_string(),
+            cfg: CostumeAddConfig { costumes },
+        }
+    }
+
+    fn conflict_kinds(a: CostumeConfig, b: CostumeConfig) -> Vec<ConflictKind> {
+        let sources = vec![source("a.json", vec![a]), source("b.json", vec![b])];
+
+        match merge_configs(sources, false) {
+            Err(CosprmError::ConfigConflict { conflicts }) => conflicts
+                .into_iter()
+                .map(|conflict| conflict.kind)
+                .collect(),
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => Vec::new(),
+        }
+    }
+
+    #[test]
+    fn finds_model_index_conflicts() {
+        let kinds = conflict_kinds(
+            costume(5, "c_cha_900", "Minato", "c_costume_900"),
+            costume(5, "c_cha_901", "Minato", "c_costume_901"),
+        );
+        assert_eq!(kinds, vec![ConflictKind::ModelIndex]);
+    }
+
+    #[test]
+    fn finds_cha_id_name_conflicts() {
+        let kinds = conflict_kinds(
+            costume(5, "c_cha_900", "Minato", "c_costume_900"),
+            costume(6, "c_cha_900", "Kushina", "c_costume_901"),
+        );
+        assert_eq!(kinds, vec![ConflictKind::ChaIdName]);
+    }
+
+    #[test]
+    fn allows_a_shared_cha_id_with_the_same_name() {
+        let kinds = conflict_kinds(
+            costume(5, "c_cha_900", "Minato", "c_costume_900"),
+            costume(6, "c_cha_900", "Minato", "c_costume_901"),
+        );
+        assert!(kinds.is_empty());
+    }
+
+    #[test]
+    fn finds_costume_id_conflicts() {
+        let kinds = conflict_kinds(
+            costume(5, "c_cha_900", "Minato", "c_costume_900"),
+            costume(6, "c_cha_901", "Minato", "c_costume_900"),
+        );
+        assert_eq!(kinds, vec![ConflictKind::CostumeId]);
+    }
+
+    #[test]
+    fn ignores_unset_ids() {
+        let kinds = conflict_kinds(costume(0, "", "Minato", ""), costume(0, "", "Minato", ""));
+        assert!(kinds.is_empty());
+    }
+
+    #[test]
+    fn resolves_by_priority() {
+        let sources = vec![
+            source(
+                "low.json",
+                vec![
+                    costume(5, "c_cha_900", "Minato", "c_costume_900"),
+                    costume(6, "c_cha_901", "Minato", "c_costume_901"),
+                ],
+            ),
+            source(
+                "high.json",
+                vec![costume(5, "c_cha_902", "Minato", "c_costume_902")],
+            ),
+        ];
+
+        let merged = merge_configs(sources, true).unwrap();
+
+        let cha_ids = merged
+            .cfg
+            .costumes
+            .iter()
+            .map(|costume| costume.cha_id.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(cha_ids, vec!["c_cha_901", "c_cha_902"]);
+        assert_eq!(merged.resolved.len(), 1);
+        assert_eq!(merged.resolved[0].kind, ConflictKind::ModelIndex);
+        assert_eq!(merged.resolved[0].files, vec!["low.json", "high.json"]);
+    }
+}
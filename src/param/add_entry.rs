@@ -1,160 +1,335 @@
-use crate::cfg::CostumeAddConfig;
+use crate::cfg::{CostumeAddConfig, CostumeConfig, PlayerSettingSelector};
+use crate::error::{CosprmError, Result};
+use crate::lock::IdLock;
 use nuccbin::{
     nucc_binary::{
-        CharacterSelectParam, CostumeBreakParam, CostumeParam, MessageInfo, NuccBinaryParsed,
-        PlayerIcon, PlayerSettingParam,
+        CharacterSelectParam, CostumeBreakParam, CostumeParam, MessageInfo, PlayerIcon,
+        PlayerSettingParam,
     },
     NuccBinaryType,
 };
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{Map, Value};
 
-use std::collections::HashMap;
 use super::calc_crc32;
+use super::nucc_binary_handler::{
+    get_nucc_binaries_of_type_mut, get_nucc_binary_mut, get_table, NuccBinaries,
+};
+use super::patch::{changed_fields, patch_entry};
+use super::report::*;
+use super::searchcode::{next_searchcode, parse_searchcode};
+
+/// Options for how costumes that are already installed are treated
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AddOptions {
+    /// Reconcile existing entries with the config instead of skipping them
+    pub update: bool,
+}
 
 pub fn add_message_info_entry(
-    nucc_binaries: &mut HashMap<NuccBinaryType, Box<dyn NuccBinaryParsed>>,
+    nucc_binaries: &mut NuccBinaries,
     cfg: &CostumeAddConfig,
-) {
-    let message_info = nucc_binaries
-        .get_mut(&NuccBinaryType::MessageInfo)
-        .unwrap()
-        .downcast_mut::<MessageInfo>()
-        .unwrap();
-
-    let mut entries = Vec::new();
-
-    for costume in cfg.costumes.iter() {
-        let costume_name_exists = message_info.entries.iter().any(|entry| {
-            entry.text3 == costume.costume_name && entry.crc32 == calc_crc32(&costume.costume_id)
-        });
-        let char_name_exists = message_info.entries.iter().any(|entry| {
-            entry.text3 == costume.char_name && entry.crc32 == calc_crc32(&costume.cha_id)
-        });
-
-        let name_entry = message_info
-            .entries
-            .iter_mut()
-            .find(|entry| entry.crc32 == [246, 160, 24, 181]) // Some random crc32 value that exists for a costume name
-            .unwrap();
-
-        let mut chara_name_entry = name_entry;
-        chara_name_entry.crc32 = calc_crc32(&costume.cha_id);
-        chara_name_entry.text3 = costume.char_name.clone();
-
+    options: &AddOptions,
+    report: &mut ChangeReport,
+) -> Result<()> {
+    // Every language has its own MessageInfo table, so each one gets its own entries
+    for (key, nucc_binary) in
+        get_nucc_binaries_of_type_mut(nucc_binaries, NuccBinaryType::MessageInfo)
+    {
+        let language = key.language();
+
+        let message_info = nucc_binary.table_mut::<MessageInfo>(NuccBinaryType::MessageInfo)?;
+
+        let mut entries = Vec::new();
+        let mut updated = false;
+
+        for costume in cfg.costumes.iter() {
+            let char_name = costume.char_name_for(language.as_deref());
+            let costume_name = costume.costume_name_for(language.as_deref());
+
+            // Rewrite the text of names that were already added
+            if options.update {
+                for (id, name) in [
+                    (&costume.cha_id, char_name),
+                    (&costume.costume_id, costume_name),
+                ] {
+                    let crc32 = calc_crc32(id);
+
+                    for entry in message_info
+                        .entries
+                        .iter_mut()
+                        .filter(|entry| entry.crc32 == crc32 && entry.text3 != name)
+                    {
+                        report.updated.push(UpdateRow {
+                            table: "MessageInfo".to_string(),
+                            entry: format!("[{}] {}", language.as_deref().unwrap_or("?"), id),
+                            field: "text".to_string(),
+                            old: entry.text3.clone(),
+                            new: name.to_string(),
+                        });
+                        entry.text3 = name.to_string();
+                        updated = true;
+                    }
+                }
+            }
 
-        let mut costume_name_entry = chara_name_entry.clone();
-        costume_name_entry.crc32 = calc_crc32(&costume.costume_id);
-        costume_name_entry.text3 = costume.costume_name.clone();
+            // Rows are found by crc32 alone, a changed name is only rewritten with --update
+            let char_name_exists = message_info
+                .entries
+                .iter()
+                .chain(entries.iter())
+                .any(|entry| entry.crc32 == calc_crc32(&costume.cha_id));
+            let costume_name_exists = message_info
+                .entries
+                .iter()
+                .chain(entries.iter())
+                .any(|entry| entry.crc32 == calc_crc32(&costume.costume_id));
+
+            // Some random crc32 value that exists for a costume name, unless the config names one
+            let template_crc32 = match &costume.template().message_info {
+                Some(message_id) => calc_crc32(message_id),
+                None => [246, 160, 24, 181],
+            };
+
+            let name_entry = message_info
+                .entries
+                .iter()
+                .find(|entry| entry.crc32 == template_crc32)
+                .ok_or_else(|| {
+                    CosprmError::entry_not_found(
+                        NuccBinaryType::MessageInfo,
+                        costume.label(),
+                        format!(
+                            "template message with crc32 {:?} is missing",
+                            template_crc32
+                        ),
+                    )
+                })?;
+
+            let mut chara_name_entry = name_entry.clone();
+            chara_name_entry.crc32 = calc_crc32(&costume.cha_id);
+            chara_name_entry.text3 = char_name.to_string();
+
+            let mut costume_name_entry = name_entry.clone();
+            costume_name_entry.crc32 = calc_crc32(&costume.costume_id);
+            costume_name_entry.text3 = costume_name.to_string();
+
+            if !char_name_exists {
+                report.message_info.push(MessageInfoRow {
+                    language: language.clone(),
+                    id: costume.cha_id.clone(),
+                    crc32: chara_name_entry.crc32,
+                    text: chara_name_entry.text3.clone(),
+                });
+                entries.push(chara_name_entry);
+            }
 
-        if char_name_exists {
-            continue;
-        }
-        entries.push(chara_name_entry.clone());
-     
-        if costume_name_exists {
-            continue;
+            if !costume_name_exists {
+                report.message_info.push(MessageInfoRow {
+                    language: language.clone(),
+                    id: costume.costume_id.clone(),
+                    crc32: costume_name_entry.crc32,
+                    text: costume_name_entry.text3.clone(),
+                });
+                entries.push(costume_name_entry);
+            }
         }
-        entries.push(costume_name_entry.clone());
 
+        if !entries.is_empty() || updated {
+            message_info.entries.extend(entries);
+            nucc_binary.mark_dirty();
+        }
     }
 
-    message_info.entries.extend(entries);
+    Ok(())
 }
 
 pub fn add_player_setting_entry(
-    nucc_binaries: &mut HashMap<NuccBinaryType, Box<dyn NuccBinaryParsed>>,
+    nucc_binaries: &mut NuccBinaries,
     cfg: &CostumeAddConfig,
-) {
-    let player_setting = nucc_binaries
-        .get_mut(&NuccBinaryType::PlayerSettingParam)
-        .and_then(|param| param.downcast_mut::<PlayerSettingParam>())
-        .expect("Failed to retrieve PlayerSettingParam");
+    lock: &mut IdLock,
+    report: &mut ChangeReport,
+) -> Result<()> {
+    let param = get_nucc_binary_mut(nucc_binaries, NuccBinaryType::PlayerSettingParam).ok_or(
+        CosprmError::MissingTable {
+            nucc_type: NuccBinaryType::PlayerSettingParam,
+        },
+    )?;
 
-    let mut highest_id = player_setting
-        .entries
-        .iter()
-        .map(|entry| entry.player_setting_id)
-        .max()
-        .unwrap_or_default();
+    let player_setting =
+        param.table_mut::<PlayerSettingParam>(NuccBinaryType::PlayerSettingParam)?;
 
-        let mut sorted_costumes = cfg.costumes.clone();
-        sorted_costumes.sort_by(|a, b| {
-            a.characode
-                .cmp(&b.characode)
-                .then(a.model_index.cmp(&b.model_index))
-        });
+    let mut added = false;
+
+    let mut sorted_costumes = cfg.costumes.clone();
+    sorted_costumes.sort_by(|a, b| {
+        a.characode
+            .cmp(&b.characode)
+            .then(a.model_index.cmp(&b.model_index))
+    });
 
     for costume in sorted_costumes.iter() {
-         let not_exist = player_setting.entries.iter().any(|entry| {
+        let existing = player_setting.entries.iter().find(|entry| {
             entry.cha_b_id == costume.cha_id
                 && entry.duel_player_param_model_index == costume.model_index
         });
 
+        // Only push if the entry doesn't already exist, but keep the lock in step with it
+        if let Some(existing) = existing {
+            lock.record_player_setting(
+                &costume.lock_key(),
+                existing.player_setting_id as u32,
+                &existing.searchcode,
+            );
+            continue;
+        }
 
         let main_entry = player_setting
             .entries
-            .iter_mut()
+            .iter()
             .filter(|entry| entry.searchcode.contains(&costume.characode))
             .max_by_key(|entry| entry.player_setting_id)
-            .unwrap();
-
-        
-        let mut entry = main_entry.clone();
+            .ok_or_else(|| {
+                CosprmError::entry_not_found(
+                    NuccBinaryType::PlayerSettingParam,
+                    costume.label(),
+                    format!(
+                        "no entry with a searchcode containing {}",
+                        costume.characode
+                    ),
+                )
+            })?;
+
+        let characode_index = main_entry.characode_index;
+
+        let mut entry = match &costume.template().player_setting_param {
+            Some(selector) => {
+                let index = find_player_setting_template(
+                    player_setting,
+                    selector,
+                    NuccBinaryType::PlayerSettingParam,
+                    costume,
+                )?;
+                player_setting.entries[index].clone()
+            }
+            None => main_entry.clone(),
+        };
 
-        entry.player_setting_id = highest_id + 1;
         entry.duel_player_param_model_index = costume.model_index;
-
-        entry.searchcode = format!(
-            "{}{:02}",
-            &entry.searchcode.chars().take(4).collect::<String>(),
-            entry.searchcode.chars().nth(5).unwrap().to_digit(10).unwrap() + 1
-        );
-
+        entry.characode_index = characode_index;
         entry.cha_b_id = costume.cha_id.clone();
 
-        highest_id += 1; // Increment the highest id for the next entry
+        let locked = lock.get(&costume.lock_key()).cloned().unwrap_or_default();
+
+        // Reuse the locked id unless something else has taken it since
+        entry.player_setting_id = match locked.player_setting_id {
+            Some(id)
+                if !player_setting
+                    .entries
+                    .iter()
+                    .any(|entry| entry.player_setting_id as u32 == id) =>
+            {
+                id as _
+            }
+            _ => {
+                let highest_id = player_setting
+                    .entries
+                    .iter()
+                    .map(|entry| entry.player_setting_id as u32)
+                    .chain(lock.reserved_player_setting_ids(&costume.lock_key()))
+                    .max()
+                    .unwrap_or_default();
+                (highest_id + 1) as _
+            }
+        };
+
+        // Entries we already pushed are in the table, so each costume gets its own number
+        entry.searchcode = match locked.searchcode {
+            Some(searchcode)
+                if parse_searchcode(&searchcode)
+                    .map_or(false, |(characode, _)| characode == costume.characode)
+                    && !player_setting
+                        .entries
+                        .iter()
+                        .any(|entry| entry.searchcode == searchcode) =>
+            {
+                searchcode
+            }
+            _ => next_searchcode(
+                player_setting,
+                &costume.characode,
+                &lock.reserved_searchcodes(&costume.lock_key()),
+            )?,
+        };
+
+        lock.record_player_setting(
+            &costume.lock_key(),
+            entry.player_setting_id as u32,
+            &entry.searchcode,
+        );
 
-        // Only push if the entry doesn't already exist
-        if not_exist {
-            continue;
-        }
+        report.player_setting_param.push(PlayerSettingRow {
+            player_setting_id: entry.player_setting_id as u32,
+            searchcode: entry.searchcode.clone(),
+            cha_b_id: entry.cha_b_id.clone(),
+            characode_index: entry.characode_index as u32,
+            model_index: entry.duel_player_param_model_index,
+        });
 
         player_setting.entries.push(entry);
+        added = true;
     }
+
+    if added {
+        param.mark_dirty();
+    }
+
+    Ok(())
 }
 
 pub fn add_costume_entry(
-    nucc_binaries: &mut HashMap<NuccBinaryType, Box<dyn NuccBinaryParsed>>,
+    nucc_binaries: &mut NuccBinaries,
     cfg: &CostumeAddConfig,
-) {
-    let player_setting = {
-        let player_setting_ref = nucc_binaries
-            .get(&NuccBinaryType::PlayerSettingParam)
-            .unwrap()
-            .downcast_ref::<PlayerSettingParam>()
-            .unwrap();
-        player_setting_ref.clone() // Clone the reference
-    };
-
-    let costume_param = nucc_binaries
-        .get_mut(&NuccBinaryType::CostumeParam)
-        .unwrap()
-        .downcast_mut::<CostumeParam>()
-        .unwrap();
+    options: &AddOptions,
+    lock: &mut IdLock,
+    report: &mut ChangeReport,
+) -> Result<()> {
+    // Clone so the other table can be borrowed mutably
+    let player_setting =
+        get_table::<PlayerSettingParam>(nucc_binaries, NuccBinaryType::PlayerSettingParam)?.clone();
+
+    let param = get_nucc_binary_mut(nucc_binaries, NuccBinaryType::CostumeParam).ok_or(
+        CosprmError::MissingTable {
+            nucc_type: NuccBinaryType::CostumeParam,
+        },
+    )?;
+
+    let costume_param = param.table_mut::<CostumeParam>(NuccBinaryType::CostumeParam)?;
 
     let mut entries_clone = costume_param.entries.clone();
 
+    let mut changed = false;
+
+    // New links go past every link in the table and every link promised in the lockfile
+    let locked_link_numbers = lock
+        .costumes
+        .values()
+        .flat_map(|ids| ids.costume_links.iter())
+        .filter_map(|costume_link| costume_link_number(costume_link));
+
     let mut highest_costume_link = costume_param
         .entries
         .iter()
         .map(|entry| {
-            entry
-                .costume_link
-                .split("_")
-                .last()
-                .unwrap()
-                .parse::<u32>()
-                .unwrap()
+            costume_link_number(&entry.costume_link).ok_or_else(|| {
+                CosprmError::InvalidCostumeLink {
+                    costume_link: entry.costume_link.clone(),
+                }
+            })
         })
+        .collect::<Result<Vec<u32>>>()?
+        .into_iter()
+        .chain(locked_link_numbers)
         .max()
         .unwrap_or(0)
         + 10;
@@ -174,88 +349,246 @@ pub fn add_costume_entry(
             .filter(|entry| entry.cha_b_id == costume.cha_id)
             .map(|entry| entry.characode_index)
             .next()
-            .unwrap();
+            .ok_or_else(|| {
+                CosprmError::entry_not_found(
+                    NuccBinaryType::PlayerSettingParam,
+                    costume.label(),
+                    format!("no entry with cha_b_id {}", costume.cha_id),
+                )
+            })?;
 
         let psp_entry = player_setting
             .entries
             .iter()
             .filter(|entry| entry.characode_index == characode_index)
             .min_by_key(|entry| entry.player_setting_id)
-            .unwrap();
+            .ok_or_else(|| {
+                CosprmError::entry_not_found(
+                    NuccBinaryType::PlayerSettingParam,
+                    costume.label(),
+                    "no base entry for the character",
+                )
+            })?;
 
         // We only need the base costume entry to get the index to insert the new entries after it
         let main_entry = entries_clone
             .iter()
             .filter(|entry| entry.player_setting_id == psp_entry.player_setting_id)
             .max_by_key(|entry| entry.color_index)
-            .unwrap()
+            .ok_or_else(|| {
+                CosprmError::entry_not_found(
+                    NuccBinaryType::CostumeParam,
+                    costume.label(),
+                    "no entry for the character's base costume",
+                )
+            })?
             .clone(); // Clone to avoid borrowing
 
+        // The entry was just found in entries_clone so it always has a position
         let main_entry_index = entries_clone
             .iter()
             .position(|entry| entry == &main_entry)
-            .unwrap();
+            .unwrap_or_default();
 
         // We need to find our new psp id we added in the player_setting_param
         let highest_psp_id = player_setting
             .entries
             .iter()
-            .filter(|entry| entry.cha_b_id == costume.cha_id)
+            .filter(|entry| {
+                entry.cha_b_id == costume.cha_id
+                    && entry.duel_player_param_model_index == costume.model_index
+            })
             .map(|entry| entry.player_setting_id)
             .max()
-            .unwrap();
+            .ok_or_else(|| {
+                CosprmError::entry_not_found(
+                    NuccBinaryType::PlayerSettingParam,
+                    costume.label(),
+                    format!("no entry with cha_b_id {}", costume.cha_id),
+                )
+            })?;
+
+        let template_entry = match &costume.template().costume_param {
+            Some(costume_link) => entries_clone
+                .iter()
+                .find(|entry| &entry.costume_link == costume_link)
+                .ok_or_else(|| {
+                    CosprmError::entry_not_found(
+                        NuccBinaryType::CostumeParam,
+                        costume.label(),
+                        format!("template with costume_link {} not found", costume_link),
+                    )
+                })?
+                .clone(),
+            None => main_entry.clone(),
+        };
+
+        let locked_links = lock
+            .get(&costume.lock_key())
+            .map(|ids| ids.costume_links.clone())
+            .unwrap_or_default();
 
         for i in 0..costume.color_count {
-            let mut cos_entry = main_entry.clone();
+            let mut cos_entry = template_entry.clone();
             cos_entry.player_setting_id = highest_psp_id;
             cos_entry.color_index = i as u32;
 
             // Only push if the psp id doesn't already exist AND the color index doesn't already exist
-            let not_exists = costume_param.entries.iter().any(|entry| {
+            let existing = costume_param.entries.iter().find(|entry| {
                 entry.player_setting_id == cos_entry.player_setting_id
                     && entry.costume_name == costume.cha_id
                     && entry.color_index == cos_entry.color_index
             });
 
-            if not_exists {
+            if let Some(existing) = existing {
+                lock.record_costume_link(&costume.lock_key(), i as u32, &existing.costume_link);
                 continue;
             }
 
-            cos_entry.price = 0;
-            cos_entry.unlock_condition = 1;
+            cos_entry.price = costume.price_for(cos_entry.color_index);
+            cos_entry.unlock_condition = costume.unlock_condition_for(cos_entry.color_index);
             cos_entry.costume_name = costume.cha_id.clone();
-            cos_entry.costume_link =
-                format!("COSTUME_{:05}", highest_costume_link + (10 * i as u32));
 
-            entries_clone.insert(main_entry_index + 1 + i as usize, cos_entry);
+            // Reuse the locked link unless something else has taken it since
+            cos_entry.costume_link = match locked_links.get(i as usize) {
+                Some(costume_link)
+                    if !costume_link.is_empty()
+                        && !entries_clone
+                            .iter()
+                            .any(|entry| &entry.costume_link == costume_link) =>
+                {
+                    costume_link.clone()
+                }
+                _ => {
+                    let costume_link = format!("COSTUME_{:05}", highest_costume_link);
+                    highest_costume_link += 10;
+                    costume_link
+                }
+            };
+
+            lock.record_costume_link(&costume.lock_key(), i as u32, &cos_entry.costume_link);
+
+            report.costume_param.push(CostumeParamRow {
+                player_setting_id: cos_entry.player_setting_id as u32,
+                color_index: cos_entry.color_index,
+                costume_name: cos_entry.costume_name.clone(),
+                costume_link: cos_entry.costume_link.clone(),
+                price: cos_entry.price,
+                unlock_condition: cos_entry.unlock_condition,
+            });
+
+            // Right after the costume's own lower colors, so its block stays in color order.
+            // A costume without colors yet goes right after the base costume
+            let own_colors = entries_clone
+                .iter()
+                .enumerate()
+                .filter(|(_, entry)| {
+                    entry.player_setting_id == highest_psp_id
+                        && entry.costume_name == costume.cha_id
+                })
+                .map(|(index, entry)| (index, entry.color_index))
+                .collect::<Vec<_>>();
+
+            let insert_index = own_colors
+                .iter()
+                .filter(|(_, color_index)| *color_index < i as u32)
+                .map(|(index, _)| index + 1)
+                .max()
+                .or_else(|| own_colors.first().map(|(index, _)| *index))
+                .unwrap_or(main_entry_index + 1);
+
+            entries_clone.insert(insert_index, cos_entry);
+            changed = true;
+        }
+
+        if options.update {
+            let count = entries_clone.len();
+
+            // Drop the colors past the configured color_count
+            entries_clone.retain(|entry| {
+                let surplus = entry.player_setting_id == highest_psp_id
+                    && entry.costume_name == costume.cha_id
+                    && entry.color_index >= costume.color_count as u32;
+
+                if surplus {
+                    report.updated.push(UpdateRow {
+                        table: "CostumeParam".to_string(),
+                        entry: entry.costume_link.clone(),
+                        field: "color".to_string(),
+                        old: entry.color_index.to_string(),
+                        new: "removed".to_string(),
+                    });
+                }
+
+                !surplus
+            });
+
+            if entries_clone.len() != count {
+                changed = true;
+            }
+
+            // Bring the price and unlock condition of the remaining colors in line with the config
+            for entry in entries_clone.iter_mut().filter(|entry| {
+                entry.player_setting_id == highest_psp_id && entry.costume_name == costume.cha_id
+            }) {
+                let price = costume.price_for(entry.color_index);
+                let unlock_condition = costume.unlock_condition_for(entry.color_index);
+
+                if entry.price != price {
+                    report.updated.push(UpdateRow {
+                        table: "CostumeParam".to_string(),
+                        entry: entry.costume_link.clone(),
+                        field: "price".to_string(),
+                        old: entry.price.to_string(),
+                        new: price.to_string(),
+                    });
+                    entry.price = price;
+                    changed = true;
+                }
+
+                if entry.unlock_condition != unlock_condition {
+                    report.updated.push(UpdateRow {
+                        table: "CostumeParam".to_string(),
+                        entry: entry.costume_link.clone(),
+                        field: "unlock_condition".to_string(),
+                        old: entry.unlock_condition.to_string(),
+                        new: unlock_condition.to_string(),
+                    });
+                    entry.unlock_condition = unlock_condition;
+                    changed = true;
+                }
+            }
         }
+    }
 
-        highest_costume_link += 10 * costume.color_count as u32;
+    if changed {
+        costume_param.entries = entries_clone;
+        param.mark_dirty();
     }
 
-    costume_param.entries = entries_clone;
+    Ok(())
 }
 
 pub fn add_icon_entry(
-    nucc_binaries: &mut HashMap<NuccBinaryType, Box<dyn NuccBinaryParsed>>,
+    nucc_binaries: &mut NuccBinaries,
     cfg: &CostumeAddConfig,
-) {
-    let player_setting = {
-        let player_setting_ref = nucc_binaries
-            .get(&NuccBinaryType::PlayerSettingParam)
-            .unwrap()
-            .downcast_ref::<PlayerSettingParam>()
-            .unwrap();
-        player_setting_ref.clone() // Clone the reference
-    };
+    options: &AddOptions,
+    report: &mut ChangeReport,
+) -> Result<()> {
+    // Clone so the other table can be borrowed mutably
+    let player_setting =
+        get_table::<PlayerSettingParam>(nucc_binaries, NuccBinaryType::PlayerSettingParam)?.clone();
 
-    let player_icon = nucc_binaries
-        .get_mut(&NuccBinaryType::PlayerIcon)
-        .unwrap()
-        .downcast_mut::<PlayerIcon>()
-        .unwrap();
+    let param = get_nucc_binary_mut(nucc_binaries, NuccBinaryType::PlayerIcon).ok_or(
+        CosprmError::MissingTable {
+            nucc_type: NuccBinaryType::PlayerIcon,
+        },
+    )?;
+
+    let player_icon = param.table_mut::<PlayerIcon>(NuccBinaryType::PlayerIcon)?;
 
     let mut entries = Vec::new();
+    let mut updated = false;
 
     for costume in cfg.costumes.iter() {
         let latest_psp_entry = player_setting
@@ -263,18 +596,61 @@ pub fn add_icon_entry(
             .iter()
             .filter(|entry| entry.searchcode.contains(&costume.characode))
             .min_by_key(|entry| entry.player_setting_id)
-            .unwrap();
+            .ok_or_else(|| {
+                CosprmError::entry_not_found(
+                    NuccBinaryType::PlayerSettingParam,
+                    costume.label(),
+                    format!(
+                        "no entry with a searchcode containing {}",
+                        costume.characode
+                    ),
+                )
+            })?;
 
         let characode_index = latest_psp_entry.characode_index;
 
-        let main_entry = player_icon
-            .entries
-            .iter_mut()
-            .filter(|entry| entry.characode_index == characode_index)
-            .max_by_key(|entry| entry.duel_player_param_costume_index)
-            .unwrap();
+        let main_entry = match &costume.template().player_icon {
+            Some(selector) => {
+                let index = find_player_setting_template(
+                    &player_setting,
+                    selector,
+                    NuccBinaryType::PlayerIcon,
+                    costume,
+                )?;
+                let template = &player_setting.entries[index];
+
+                player_icon
+                    .entries
+                    .iter()
+                    .find(|entry| {
+                        entry.characode_index == template.characode_index
+                            && entry.duel_player_param_costume_index
+                                == template.duel_player_param_model_index
+                    })
+                    .ok_or_else(|| {
+                        CosprmError::entry_not_found(
+                            NuccBinaryType::PlayerIcon,
+                            costume.label(),
+                            format!("no icon for the template {}", selector),
+                        )
+                    })?
+            }
+            None => player_icon
+                .entries
+                .iter()
+                .filter(|entry| entry.characode_index == characode_index)
+                .max_by_key(|entry| entry.duel_player_param_costume_index)
+                .ok_or_else(|| {
+                    CosprmError::entry_not_found(
+                        NuccBinaryType::PlayerIcon,
+                        costume.label(),
+                        "no icon entry for the character",
+                    )
+                })?,
+        };
 
         let mut entry = main_entry.clone();
+        entry.characode_index = characode_index;
 
         if costume.iconcode.is_empty() {
             continue;
@@ -283,41 +659,64 @@ pub fn add_icon_entry(
         entry.icon_id = costume.iconcode.clone();
         entry.duel_player_param_costume_index = costume.model_index;
 
-        let not_exist = player_icon.entries.iter().any(|entry| {
+        let existing = player_icon.entries.iter_mut().find(|entry| {
             entry.duel_player_param_costume_index == costume.model_index
                 && entry.characode_index == characode_index
         });
 
-        if not_exist {
+        if let Some(existing) = existing {
+            // Swap in the configured icon if it changed
+            if options.update && existing.icon_id != costume.iconcode {
+                report.updated.push(UpdateRow {
+                    table: "PlayerIcon".to_string(),
+                    entry: format!(
+                        "characode_index {} costume_index {}",
+                        existing.characode_index as u32, costume.model_index
+                    ),
+                    field: "icon_id".to_string(),
+                    old: existing.icon_id.clone(),
+                    new: costume.iconcode.clone(),
+                });
+                existing.icon_id = costume.iconcode.clone();
+                updated = true;
+            }
             continue;
         }
 
+        report.player_icon.push(PlayerIconRow {
+            characode_index: entry.characode_index as u32,
+            costume_index: entry.duel_player_param_costume_index,
+            icon_id: entry.icon_id.clone(),
+        });
 
         entries.push(entry.clone());
     }
 
-    player_icon.entries.extend(entries);
+    if !entries.is_empty() || updated {
+        player_icon.entries.extend(entries);
+        param.mark_dirty();
+    }
+
+    Ok(())
 }
 
 pub fn add_character_select_entry(
-    nucc_binaries: &mut HashMap<NuccBinaryType, Box<dyn NuccBinaryParsed>>,
+    nucc_binaries: &mut NuccBinaries,
     cfg: &CostumeAddConfig,
-) {
-    let player_setting = {
-        let player_setting_ref = nucc_binaries
-            .get(&NuccBinaryType::PlayerSettingParam)
-            .unwrap()
-            .downcast_ref::<PlayerSettingParam>()
-            .unwrap();
-        player_setting_ref.clone() // Clone the reference
-    };
+    report: &mut ChangeReport,
+) -> Result<()> {
+    // Clone so the other table can be borrowed mutably
+    let player_setting =
+        get_table::<PlayerSettingParam>(nucc_binaries, NuccBinaryType::PlayerSettingParam)?.clone();
 
+    let param = get_nucc_binary_mut(nucc_binaries, NuccBinaryType::CharacterSelectParam).ok_or(
+        CosprmError::MissingTable {
+            nucc_type: NuccBinaryType::CharacterSelectParam,
+        },
+    )?;
 
-    let character_select = nucc_binaries
-        .get_mut(&NuccBinaryType::CharacterSelectParam)
-        .unwrap()
-        .downcast_mut::<CharacterSelectParam>()
-        .unwrap();
+    let character_select =
+        param.table_mut::<CharacterSelectParam>(NuccBinaryType::CharacterSelectParam)?;
 
     let mut entries = Vec::new();
 
@@ -326,40 +725,95 @@ pub fn add_character_select_entry(
         let latest_psp_entry = player_setting
             .entries
             .iter()
-            .filter(|entry| entry.cha_b_id == costume.cha_id && entry.duel_player_param_model_index == costume.model_index)
+            .filter(|entry| {
+                entry.cha_b_id == costume.cha_id
+                    && entry.duel_player_param_model_index == costume.model_index
+            })
             .min_by_key(|entry| entry.player_setting_id)
-            .unwrap();
+            .ok_or_else(|| {
+                CosprmError::entry_not_found(
+                    NuccBinaryType::PlayerSettingParam,
+                    costume.label(),
+                    "no entry was added for the costume",
+                )
+            })?;
 
         let main_psp_entry = player_setting
             .entries
             .iter()
             .filter(|entry| entry.characode_index == latest_psp_entry.characode_index)
             .min_by_key(|entry| entry.player_setting_id)
-            .unwrap();
-
+            .ok_or_else(|| {
+                CosprmError::entry_not_found(
+                    NuccBinaryType::PlayerSettingParam,
+                    costume.label(),
+                    "no base entry for the character",
+                )
+            })?;
 
         let main_base_entry = character_select
             .entries
             .iter()
             .filter(|entry| entry.searchcode == main_psp_entry.searchcode.clone())
             .min_by_key(|entry| entry.costume_slot_index)
-            .unwrap();
+            .ok_or_else(|| {
+                CosprmError::entry_not_found(
+                    NuccBinaryType::CharacterSelectParam,
+                    costume.label(),
+                    format!("no entry with searchcode {}", main_psp_entry.searchcode),
+                )
+            })?;
 
-        
         let latest_base_entry = character_select
             .entries
             .iter()
-            .filter(|entry| entry.page_index == main_base_entry.page_index && entry.slot_index == main_base_entry.slot_index)
+            .filter(|entry| {
+                entry.page_index == main_base_entry.page_index
+                    && entry.slot_index == main_base_entry.slot_index
+            })
             .max_by_key(|entry| entry.costume_slot_index)
-            .unwrap();
-
-        
-        let mut entry = latest_base_entry.clone();
+            .ok_or_else(|| {
+                CosprmError::entry_not_found(
+                    NuccBinaryType::CharacterSelectParam,
+                    costume.label(),
+                    "no entry in the character's slot",
+                )
+            })?;
+
+        let mut entry = match &costume.template().character_select_param {
+            Some(selector) => {
+                let index = find_player_setting_template(
+                    &player_setting,
+                    selector,
+                    NuccBinaryType::CharacterSelectParam,
+                    costume,
+                )?;
+                let template = &player_setting.entries[index];
+
+                character_select
+                    .entries
+                    .iter()
+                    .filter(|entry| entry.searchcode == template.searchcode)
+                    .min_by_key(|entry| entry.costume_slot_index)
+                    .ok_or_else(|| {
+                        CosprmError::entry_not_found(
+                            NuccBinaryType::CharacterSelectParam,
+                            costume.label(),
+                            format!("no entry for the template {}", selector),
+                        )
+                    })?
+                    .clone()
+            }
+            None => latest_base_entry.clone(),
+        };
 
+        // The new costume always goes in the character's own slot
+        entry.page_index = latest_base_entry.page_index;
+        entry.slot_index = latest_base_entry.slot_index;
         entry.costume_slot_index = costume.model_index as u32;
         entry.searchcode = latest_psp_entry.searchcode.clone();
         entry.costume_name = costume.costume_id.clone();
-        
+
         let not_exist = character_select.entries.iter().any(|e| {
             e.costume_name == costume.costume_id
                 && e.page_index == entry.page_index
@@ -370,39 +824,48 @@ pub fn add_character_select_entry(
         if not_exist {
             continue;
         }
-        
+
+        report.character_select_param.push(CharacterSelectRow {
+            page_index: entry.page_index as u32,
+            slot_index: entry.slot_index as u32,
+            costume_slot_index: entry.costume_slot_index,
+            searchcode: entry.searchcode.clone(),
+            costume_name: entry.costume_name.clone(),
+        });
+
         entries.push(entry);
     }
 
-    character_select.entries.extend(entries);
+    if !entries.is_empty() {
+        character_select.entries.extend(entries);
+        param.mark_dirty();
+    }
+
+    Ok(())
 }
 
 pub fn add_costume_break_entry(
-    nucc_binaries: &mut HashMap<NuccBinaryType, Box<dyn NuccBinaryParsed>>,
+    nucc_binaries: &mut NuccBinaries,
     cfg: &CostumeAddConfig,
-) {
-    let player_setting = {
-        let player_setting_ref = nucc_binaries
-            .get(&NuccBinaryType::PlayerSettingParam)
-            .unwrap()
-            .downcast_ref::<PlayerSettingParam>()
-            .unwrap();
-        player_setting_ref.clone() // Clone the reference
-    };
+    options: &AddOptions,
+    report: &mut ChangeReport,
+) -> Result<()> {
+    // Clone so the other table can be borrowed mutably
+    let player_setting =
+        get_table::<PlayerSettingParam>(nucc_binaries, NuccBinaryType::PlayerSettingParam)?.clone();
+
+    let param = get_nucc_binary_mut(nucc_binaries, NuccBinaryType::CostumeBreakParam).ok_or(
+        CosprmError::MissingTable {
+            nucc_type: NuccBinaryType::CostumeBreakParam,
+        },
+    )?;
 
-    let costume_break = nucc_binaries
-        .get_mut(&NuccBinaryType::CostumeBreakParam)
-        .unwrap()
-        .downcast_mut::<CostumeBreakParam>()
-        .unwrap();
+    let costume_break = param.table_mut::<CostumeBreakParam>(NuccBinaryType::CostumeBreakParam)?;
 
     let mut entries = Vec::new();
+    let mut updated = false;
 
     for costume in cfg.costumes.iter() {
-        if costume.has_costume_break { // Only add costume break entries if set to true
-            continue;
-        }
-
         let searchcode = format!("{}00", &costume.characode);
 
         let psp_entry = player_setting
@@ -410,31 +873,216 @@ pub fn add_costume_break_entry(
             .iter()
             .filter(|entry| entry.searchcode == searchcode)
             .min_by_key(|entry| entry.player_setting_id)
-            .unwrap();
+            .ok_or_else(|| {
+                CosprmError::entry_not_found(
+                    NuccBinaryType::PlayerSettingParam,
+                    costume.label(),
+                    format!("no entry with searchcode {}", searchcode),
+                )
+            })?;
 
         let characode_index = psp_entry.characode_index;
+        let entry_label = format!(
+            "characode_index {} costume_index {}",
+            characode_index as u32, costume.model_index
+        );
 
-        let main_entry = costume_break
-            .entries
-            .iter_mut()
-            .filter(|entry| entry.characode_index == characode_index)
-            .min_by_key(|entry| entry.costume_index)
-            .unwrap();
-
-        let mut entry = main_entry.clone();
-        entry.costume_index = costume.model_index as u32;
-
-        let not_exist = costume_break.entries.iter().any(|entry| {
+        let existing = costume_break.entries.iter().position(|entry| {
             entry.costume_index == costume.model_index as u32
                 && entry.characode_index == characode_index
         });
 
-        if not_exist {
+        if !costume.costume_break_enabled() {
+            // A costume that no longer breaks loses the entry it was given before
+            if let (Some(index), true) = (existing, options.update) {
+                costume_break.entries.remove(index);
+                report.updated.push(UpdateRow {
+                    table: "CostumeBreakParam".to_string(),
+                    entry: entry_label,
+                    field: "costume_break".to_string(),
+                    old: "enabled".to_string(),
+                    new: "disabled".to_string(),
+                });
+                updated = true;
+            }
             continue;
         }
 
-        entries.push(entry.clone());
+        if let Some(index) = existing {
+            if !options.update {
+                continue;
+            }
+
+            let existing = &mut costume_break.entries[index];
+            let overrides = costume_break_overrides(costume)?;
+            let mut patched = patch_costume_break(existing, &overrides, costume)?;
+
+            if let Some(broken_modelcode) = broken_modelcode(costume) {
+                patched.modelcode = broken_modelcode.to_string();
+            }
+
+            for (field, old, new) in changed_fields(existing, &patched) {
+                report.updated.push(UpdateRow {
+                    table: "CostumeBreakParam".to_string(),
+                    entry: entry_label.clone(),
+                    field,
+                    old: old.to_string(),
+                    new: new.to_string(),
+                });
+                updated = true;
+            }
+
+            *existing = patched;
+            continue;
+        }
+
+        let main_entry = match &costume.template().costume_break_param {
+            Some(selector) => {
+                let index = find_player_setting_template(
+                    &player_setting,
+                    selector,
+                    NuccBinaryType::CostumeBreakParam,
+                    costume,
+                )?;
+                let template = &player_setting.entries[index];
+
+                costume_break
+                    .entries
+                    .iter()
+                    .find(|entry| {
+                        entry.characode_index == template.characode_index
+                            && entry.costume_index == template.duel_player_param_model_index as u32
+                    })
+                    .ok_or_else(|| {
+                        CosprmError::entry_not_found(
+                            NuccBinaryType::CostumeBreakParam,
+                            costume.label(),
+                            format!("no costume break entry for the template {}", selector),
+                        )
+                    })?
+            }
+            None => costume_break
+                .entries
+                .iter()
+                .filter(|entry| entry.characode_index == characode_index)
+                .min_by_key(|entry| entry.costume_index)
+                .ok_or_else(|| {
+                    CosprmError::entry_not_found(
+                        NuccBinaryType::CostumeBreakParam,
+                        costume.label(),
+                        "no costume break entry for the character",
+                    )
+                })?,
+        };
+
+        let overrides = costume_break_overrides(costume)?;
+
+        let mut entry = patch_costume_break(main_entry, &overrides, costume)?;
+        entry.characode_index = characode_index;
+        entry.costume_index = costume.model_index as u32;
+
+        if let Some(broken_modelcode) = broken_modelcode(costume) {
+            entry.modelcode = broken_modelcode.to_string();
+        }
+
+        report.costume_break_param.push(CostumeBreakRow {
+            characode_index: entry.characode_index as u32,
+            costume_index: entry.costume_index,
+        });
+
+        entries.push(entry);
+    }
+
+    if !entries.is_empty() || updated {
+        costume_break.entries.extend(entries);
+        param.mark_dirty();
+    }
+
+    Ok(())
+}
+
+/// The fields a costume's `costume_break` block overrides on a CostumeBreakParam entry, by the
+/// names nuccbin gives them. The broken modelcode is set on the entry directly
+pub fn costume_break_overrides(costume: &CostumeConfig) -> Result<Map<String, Value>> {
+    let costume_break = match &costume.costume_break {
+        Some(costume_break) => costume_break,
+        None => return Ok(Map::new()),
+    };
+
+    for identity in ["characode_index", "costume_index"] {
+        if costume_break.fields.contains_key(identity) {
+            return Err(invalid_costume_break(
+                costume,
+                format!(
+                    "{} is set from the costume and can't be overridden",
+                    identity
+                ),
+            ));
+        }
+    }
+
+    Ok(costume_break.fields.clone())
+}
+
+/// The modelcode the costume swaps to when it tears, if the config names one
+fn broken_modelcode(costume: &CostumeConfig) -> Option<&str> {
+    costume
+        .costume_break
+        .as_ref()
+        .and_then(|costume_break| costume_break.broken_modelcode.as_deref())
+}
+
+fn patch_costume_break<T: Clone + Serialize + DeserializeOwned>(
+    entry: &T,
+    overrides: &Map<String, Value>,
+    costume: &CostumeConfig,
+) -> Result<T> {
+    if overrides.is_empty() {
+        // Nothing to set, so skip the round trip through JSON
+        return Ok(entry.clone());
+    }
+
+    patch_entry(entry, overrides).map_err(|e| invalid_costume_break(costume, e))
+}
+
+fn invalid_costume_break(costume: &CostumeConfig, message: impl ToString) -> CosprmError {
+    CosprmError::InvalidOverride {
+        nucc_type: NuccBinaryType::CostumeBreakParam,
+        costume: costume.label(),
+        message: message.to_string(),
     }
+}
+
+/// The number at the end of a costume_link like COSTUME_00120
+fn costume_link_number(costume_link: &str) -> Option<u32> {
+    costume_link
+        .split('_')
+        .last()
+        .and_then(|number| number.parse::<u32>().ok())
+}
 
-    costume_break.entries.extend(entries);
+/// Find the index of the PlayerSettingParam entry a template selector names
+fn find_player_setting_template(
+    player_setting: &PlayerSettingParam,
+    selector: &PlayerSettingSelector,
+    nucc_type: NuccBinaryType,
+    costume: &CostumeConfig,
+) -> Result<usize> {
+    player_setting
+        .entries
+        .iter()
+        .position(|entry| match selector {
+            PlayerSettingSelector::Id(id) => entry.player_setting_id as u32 == *id,
+            PlayerSettingSelector::Searchcode(searchcode) => &entry.searchcode == searchcode,
+        })
+        .ok_or_else(|| {
+            CosprmError::entry_not_found(
+                nucc_type,
+                costume.label(),
+                format!(
+                    "template PlayerSettingParam entry with {} not found",
+                    selector
+                ),
+            )
+        })
 }
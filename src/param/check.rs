@@ -0,0 +1,445 @@
+use crate::error::Result;
+use nuccbin::{
+    nucc_binary::{
+        CharacterSelectParam, CostumeBreakParam, CostumeParam, MessageInfo, PlayerIcon,
+        PlayerSettingParam,
+    },
+    NuccBinaryType,
+};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::hash::Hash;
+
+use super::calc_crc32;
+use super::nucc_binary_handler::{get_table, NuccBinaries};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// Unusual, but the game handles it
+    Info,
+    /// Shows up wrong in game, e.g. a blank name
+    Warning,
+    /// A reference to a row that doesn't exist, or two rows the game can't tell apart
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Info => write!(f, "info"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// A broken or suspicious relationship between rows of the param tables
+#[derive(Debug, Clone, Serialize)]
+pub struct IntegrityIssue {
+    pub severity: Severity,
+    pub table: String,
+    pub entry: String,
+    pub message: String,
+}
+
+impl fmt::Display for IntegrityIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "[{}] {} {}: {}",
+            self.severity, self.table, self.entry, self.message
+        )
+    }
+}
+
+/// Cross-reference the tables through the same fields the costumes are added with:
+/// player_setting_id, cha_b_id, characode_index with the model index, searchcode and
+/// the crc32 of message ids. Tables other than PlayerSettingParam are skipped when missing.
+/// The issues are sorted from most to least severe
+pub fn check_integrity(nucc_binaries: &NuccBinaries) -> Result<Vec<IntegrityIssue>> {
+    let player_setting =
+        get_table::<PlayerSettingParam>(nucc_binaries, NuccBinaryType::PlayerSettingParam)?;
+    let costume_param = get_table::<CostumeParam>(nucc_binaries, NuccBinaryType::CostumeParam).ok();
+    let player_icon = get_table::<PlayerIcon>(nucc_binaries, NuccBinaryType::PlayerIcon).ok();
+    let character_select =
+        get_table::<CharacterSelectParam>(nucc_binaries, NuccBinaryType::CharacterSelectParam).ok();
+    let costume_break =
+        get_table::<CostumeBreakParam>(nucc_binaries, NuccBinaryType::CostumeBreakParam).ok();
+
+    let messages = MessageIndex::new(nucc_binaries);
+
+    let mut issues = Vec::new();
+    let mut issue = |severity: Severity, table: &str, entry: String, message: String| {
+        issues.push(IntegrityIssue {
+            severity,
+            table: table.to_string(),
+            entry,
+            message,
+        })
+    };
+
+    // PlayerSettingParam
+    let psp_ids = player_setting
+        .entries
+        .iter()
+        .map(|entry| entry.player_setting_id as u32)
+        .collect::<HashSet<_>>();
+    let searchcodes = player_setting
+        .entries
+        .iter()
+        .map(|entry| entry.searchcode.as_str())
+        .collect::<HashSet<_>>();
+    let models = player_setting
+        .entries
+        .iter()
+        .map(|entry| {
+            (
+                entry.characode_index as u32,
+                entry.duel_player_param_model_index,
+            )
+        })
+        .collect::<HashSet<_>>();
+
+    for id in duplicates(
+        player_setting
+            .entries
+            .iter()
+            .map(|entry| entry.player_setting_id as u32),
+    ) {
+        issue(
+            Severity::Error,
+            "PlayerSettingParam",
+            format!("id {}", id),
+            "the player_setting_id is used by more than one entry".to_string(),
+        );
+    }
+
+    for searchcode in duplicates(
+        player_setting
+            .entries
+            .iter()
+            .map(|entry| entry.searchcode.clone()),
+    ) {
+        issue(
+            Severity::Error,
+            "PlayerSettingParam",
+            searchcode,
+            "the searchcode is used by more than one entry".to_string(),
+        );
+    }
+
+    for (characode_index, model_index) in duplicates(player_setting.entries.iter().map(|entry| {
+        (
+            entry.characode_index as u32,
+            entry.duel_player_param_model_index,
+        )
+    })) {
+        issue(
+            Severity::Warning,
+            "PlayerSettingParam",
+            format!("characode_index {}", characode_index),
+            format!("model index {} is used by more than one entry", model_index),
+        );
+    }
+
+    for entry in &player_setting.entries {
+        let label = format!("{} (id {})", entry.searchcode, entry.player_setting_id);
+
+        if let Some(message) = messages.missing_text(&entry.cha_b_id, Severity::Warning) {
+            issue(
+                message.0,
+                "PlayerSettingParam",
+                label.clone(),
+                format!("cha_b_id {} {}", entry.cha_b_id, message.1),
+            );
+        }
+
+        if let Some(costume_param) = costume_param {
+            if !costume_param
+                .entries
+                .iter()
+                .any(|costume| costume.player_setting_id == entry.player_setting_id)
+            {
+                issue(
+                    Severity::Warning,
+                    "PlayerSettingParam",
+                    label.clone(),
+                    "no CostumeParam entry belongs to it".to_string(),
+                );
+            }
+        }
+
+        if let Some(character_select) = character_select {
+            if !character_select
+                .entries
+                .iter()
+                .any(|slot| slot.searchcode == entry.searchcode)
+            {
+                issue(
+                    Severity::Info,
+                    "PlayerSettingParam",
+                    label,
+                    "it has no CharacterSelectParam slot".to_string(),
+                );
+            }
+        }
+    }
+
+    // CostumeParam
+    if let Some(costume_param) = costume_param {
+        for costume_link in duplicates(
+            costume_param
+                .entries
+                .iter()
+                .map(|entry| entry.costume_link.clone()),
+        ) {
+            issue(
+                Severity::Error,
+                "CostumeParam",
+                costume_link,
+                "the costume_link is used by more than one entry".to_string(),
+            );
+        }
+
+        for (player_setting_id, color_index) in duplicates(
+            costume_param
+                .entries
+                .iter()
+                .map(|entry| (entry.player_setting_id as u32, entry.color_index)),
+        ) {
+            issue(
+                Severity::Error,
+                "CostumeParam",
+                format!("player_setting_id {}", player_setting_id),
+                format!("color {} is defined more than once", color_index),
+            );
+        }
+
+        for entry in &costume_param.entries {
+            if !psp_ids.contains(&(entry.player_setting_id as u32)) {
+                issue(
+                    Severity::Error,
+                    "CostumeParam",
+                    entry.costume_link.clone(),
+                    format!(
+                        "player_setting_id {} has no PlayerSettingParam entry",
+                        entry.player_setting_id
+                    ),
+                );
+            }
+
+            // The costume select shows a blank name
+            if let Some(message) = messages.missing_text(&entry.costume_name, Severity::Error) {
+                issue(
+                    message.0,
+                    "CostumeParam",
+                    entry.costume_link.clone(),
+                    format!("costume_name {} {}", entry.costume_name, message.1),
+                );
+            }
+        }
+    }
+
+    // PlayerIcon
+    if let Some(player_icon) = player_icon {
+        let icons = player_icon.entries.iter().map(|entry| {
+            (
+                entry.characode_index as u32,
+                entry.duel_player_param_costume_index,
+            )
+        });
+
+        for (characode_index, costume_index) in duplicates(icons.clone()) {
+            issue(
+                Severity::Warning,
+                "PlayerIcon",
+                format!("characode_index {}", characode_index),
+                format!("costume index {} has more than one icon", costume_index),
+            );
+        }
+
+        for ((characode_index, costume_index), entry) in icons.zip(&player_icon.entries) {
+            if !models.contains(&(characode_index, costume_index)) {
+                issue(
+                    Severity::Error,
+                    "PlayerIcon",
+                    entry.icon_id.clone(),
+                    format!(
+                        "no PlayerSettingParam entry has characode_index {} and model index {}",
+                        characode_index, costume_index
+                    ),
+                );
+            }
+        }
+    }
+
+    // CharacterSelectParam
+    if let Some(character_select) = character_select {
+        for (page_index, slot_index, costume_slot_index) in
+            duplicates(character_select.entries.iter().map(|entry| {
+                (
+                    entry.page_index as u32,
+                    entry.slot_index as u32,
+                    entry.costume_slot_index,
+                )
+            }))
+        {
+            issue(
+                Severity::Error,
+                "CharacterSelectParam",
+                format!("page {} slot {}", page_index, slot_index),
+                format!(
+                    "costume slot {} is used by more than one entry",
+                    costume_slot_index
+                ),
+            );
+        }
+
+        for entry in &character_select.entries {
+            let label = format!(
+                "page {} slot {} costume slot {}",
+                entry.page_index, entry.slot_index, entry.costume_slot_index
+            );
+
+            if !searchcodes.contains(entry.searchcode.as_str()) {
+                issue(
+                    Severity::Error,
+                    "CharacterSelectParam",
+                    label.clone(),
+                    format!(
+                        "searchcode {} has no PlayerSettingParam entry",
+                        entry.searchcode
+                    ),
+                );
+            }
+
+            // The costume select shows a blank name
+            if let Some(message) = messages.missing_text(&entry.costume_name, Severity::Error) {
+                issue(
+                    message.0,
+                    "CharacterSelectParam",
+                    label,
+                    format!("costume_name {} {}", entry.costume_name, message.1),
+                );
+            }
+        }
+    }
+
+    // CostumeBreakParam
+    if let Some(costume_break) = costume_break {
+        let breaks = costume_break
+            .entries
+            .iter()
+            .map(|entry| (entry.characode_index as u32, entry.costume_index));
+
+        for (characode_index, costume_index) in duplicates(breaks.clone()) {
+            issue(
+                Severity::Warning,
+                "CostumeBreakParam",
+                format!("characode_index {}", characode_index),
+                format!("costume index {} is defined more than once", costume_index),
+            );
+        }
+
+        for (characode_index, costume_index) in breaks {
+            if !models.contains(&(characode_index, costume_index as i32)) {
+                issue(
+                    Severity::Error,
+                    "CostumeBreakParam",
+                    format!("characode_index {}", characode_index),
+                    format!(
+                        "no PlayerSettingParam entry has model index {}",
+                        costume_index
+                    ),
+                );
+            }
+        }
+    }
+
+    issues.sort_by(|a, b| b.severity.cmp(&a.severity));
+
+    Ok(issues)
+}
+
+/// The languages every message crc32 has text in
+struct MessageIndex {
+    languages: Vec<String>,
+    texts: HashMap<[u8; 4], HashSet<String>>,
+}
+
+impl MessageIndex {
+    fn new(nucc_binaries: &NuccBinaries) -> Self {
+        let mut languages = Vec::new();
+        let mut texts: HashMap<[u8; 4], HashSet<String>> = HashMap::new();
+
+        for (key, nucc_binary) in nucc_binaries
+            .iter()
+            .filter(|(key, _)| key.nucc_type == NuccBinaryType::MessageInfo)
+        {
+            let message_info = match nucc_binary.parsed.downcast_ref::<MessageInfo>() {
+                Some(message_info) => message_info,
+                None => continue,
+            };
+
+            let language = key.language().unwrap_or_else(|| key.filepath.clone());
+
+            for entry in &message_info.entries {
+                texts
+                    .entry(entry.crc32)
+                    .or_default()
+                    .insert(language.clone());
+            }
+
+            languages.push(language);
+        }
+
+        languages.sort();
+
+        Self { languages, texts }
+    }
+
+    /// How bad it is that a message id has no text, and in which languages. `severity` is used when
+    /// no language has it. None when every language has it, or when no MessageInfo was loaded at all
+    fn missing_text(&self, id: &str, severity: Severity) -> Option<(Severity, String)> {
+        if id.is_empty() || self.languages.is_empty() {
+            return None;
+        }
+
+        let found = self.texts.get(&calc_crc32(id));
+
+        let missing = self
+            .languages
+            .iter()
+            .filter(|language| found.map_or(true, |found| !found.contains(*language)))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        if missing.is_empty() {
+            None
+        } else if missing.len() == self.languages.len() {
+            Some((severity, "has no MessageInfo text".to_string()))
+        } else {
+            Some((
+                Severity::Info,
+                format!("has no MessageInfo text in {}", missing.join(", ")),
+            ))
+        }
+    }
+}
+
+/// The values that occur more than once, in the order they first repeat
+fn duplicates<T: Eq + Hash + Clone>(values: impl Iterator<Item = T>) -> Vec<T> {
+    let mut seen = HashSet::new();
+    let mut repeated = HashSet::new();
+    let mut duplicates = Vec::new();
+
+    for value in values {
+        if !seen.insert(value.clone()) && repeated.insert(value.clone()) {
+            duplicates.push(value);
+        }
+    }
+
+    duplicates
+}
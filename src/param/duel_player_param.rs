@@ -0,0 +1,138 @@
+use crate::cfg::CostumeConfig;
+use crate::error::{CosprmError, Result};
+use nuccbin::{nucc_binary::DuelPlayerParam, NuccBinaryType};
+
+use super::handler::{HandlerContext, TableHandler};
+use super::nucc_binary_handler::{get_nucc_binary_mut, NuccBinaries};
+use super::report::{OtherTableRow, UpdateRow};
+
+/// The suffix of the model files DuelPlayerParam lists, e.g. "nmncbod1"
+const MODEL_SUFFIX: &str = "bod1";
+
+/// Registers the modelcode of a costume in the costume list of the character's DuelPlayerParam
+/// entry, so the game loads the model for the model index
+pub struct DuelPlayerParamHandler;
+
+impl TableHandler for DuelPlayerParamHandler {
+    fn nucc_type(&self) -> NuccBinaryType {
+        NuccBinaryType::DuelPlayerParam
+    }
+
+    fn add(&self, nucc_binaries: &mut NuccBinaries, context: &mut HandlerContext) -> Result<()> {
+        let param = get_nucc_binary_mut(nucc_binaries, NuccBinaryType::DuelPlayerParam).ok_or(
+            CosprmError::MissingTable {
+                nucc_type: NuccBinaryType::DuelPlayerParam,
+            },
+        )?;
+
+        let duel_player = param.table_mut::<DuelPlayerParam>(NuccBinaryType::DuelPlayerParam)?;
+
+        let mut changed = false;
+
+        for costume in context.cfg.costumes.iter() {
+            let entry = duel_player
+                .entries
+                .iter_mut()
+                .find(|entry| entry.characode == costume.characode)
+                .ok_or_else(|| not_found(costume, "no entry for the characode"))?;
+
+            let model = format!("{}{}", costume.modelcode, MODEL_SUFFIX);
+            let entry_label = format!("{} model index {}", costume.characode, costume.model_index);
+
+            // The costume list has a fixed number of slots
+            let slot = entry
+                .costumes
+                .get_mut(costume.model_index as usize)
+                .ok_or_else(|| {
+                    not_found(
+                        costume,
+                        format!("the costume list has no slot {}", costume.model_index),
+                    )
+                })?;
+
+            if *slot == model {
+                continue;
+            }
+
+            if slot.is_empty() {
+                context.report.other.push(OtherTableRow {
+                    table: "DuelPlayerParam".to_string(),
+                    entry: entry_label,
+                    description: model.clone(),
+                });
+            } else if context.options.update {
+                context.report.updated.push(UpdateRow {
+                    table: "DuelPlayerParam".to_string(),
+                    entry: entry_label,
+                    field: "costumes".to_string(),
+                    old: slot.clone(),
+                    new: model.clone(),
+                });
+            } else {
+                // The costume would load the other model
+                return Err(CosprmError::ModelSlotTaken {
+                    characode: costume.characode.clone(),
+                    model_index: costume.model_index,
+                    model: slot.clone(),
+                });
+            }
+
+            *slot = model;
+            changed = true;
+        }
+
+        if changed {
+            param.mark_dirty();
+        }
+
+        Ok(())
+    }
+
+    fn remove(&self, nucc_binaries: &mut NuccBinaries, context: &mut HandlerContext) -> Result<()> {
+        let param = get_nucc_binary_mut(nucc_binaries, NuccBinaryType::DuelPlayerParam).ok_or(
+            CosprmError::MissingTable {
+                nucc_type: NuccBinaryType::DuelPlayerParam,
+            },
+        )?;
+
+        let duel_player = param.table_mut::<DuelPlayerParam>(NuccBinaryType::DuelPlayerParam)?;
+
+        let mut changed = false;
+
+        for costume in context.cfg.costumes.iter() {
+            let model = format!("{}{}", costume.modelcode, MODEL_SUFFIX);
+
+            // Only clear the slot while it still loads our model
+            let slot = duel_player
+                .entries
+                .iter_mut()
+                .filter(|entry| entry.characode == costume.characode)
+                .find_map(|entry| entry.costumes.get_mut(costume.model_index as usize))
+                .filter(|slot| **slot == model);
+
+            if let Some(slot) = slot {
+                slot.clear();
+                context.report.other.push(OtherTableRow {
+                    table: "DuelPlayerParam".to_string(),
+                    entry: format!("{} model index {}", costume.characode, costume.model_index),
+                    description: model,
+                });
+                changed = true;
+            }
+        }
+
+        if changed {
+            param.mark_dirty();
+        }
+
+        Ok(())
+    }
+}
+
+fn not_found(costume: &CostumeConfig, description: impl Into<String>) -> CosprmError {
+    CosprmError::entry_not_found(
+        NuccBinaryType::DuelPlayerParam,
+        costume.label(),
+        description,
+    )
+}
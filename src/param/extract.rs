@@ -0,0 +1,241 @@
+use crate::cfg::{
+    ColorConfig, CostumeConfig, LocalizedNames, DEFAULT_PRICE, DEFAULT_UNLOCK_CONDITION,
+};
+use crate::error::{CosprmError, Result};
+use nuccbin::{
+    nucc_binary::{
+        CharacterSelectParam, CostumeBreakParam, CostumeParam, MessageInfo, PlayerIcon,
+        PlayerSettingParam,
+    },
+    NuccBinaryType,
+};
+use std::collections::HashMap;
+use std::fmt;
+
+use super::calc_crc32;
+use super::list::DEFAULT_LANGUAGE;
+use super::nucc_binary_handler::{get_message_infos, get_table, NuccBinaries};
+use super::searchcode::parse_searchcode;
+
+/// The existing costume to extract
+#[derive(Debug, Clone)]
+pub enum CostumeSelector {
+    /// The PlayerSettingParam row of a characode with a model index
+    Model { characode: String, model_index: i32 },
+    /// The PlayerSettingParam row with a searchcode (e.g. "3mnt02")
+    Searchcode(String),
+}
+
+impl fmt::Display for CostumeSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CostumeSelector::Model {
+                characode,
+                model_index,
+            } => write!(f, "characode {}, model index {}", characode, model_index),
+            CostumeSelector::Searchcode(searchcode) => write!(f, "searchcode {}", searchcode),
+        }
+    }
+}
+
+/// Build the config that would add an existing costume again.
+///
+/// The names come from the MessageInfo table of `language` (eng by default), and the other
+/// languages are added to `names` where their text differs. The modelcode isn't stored in any of
+/// the tables, so it's left to the caller
+pub fn extract_costume(
+    nucc_binaries: &NuccBinaries,
+    selector: &CostumeSelector,
+    language: Option<&str>,
+) -> Result<CostumeConfig> {
+    let player_setting =
+        get_table::<PlayerSettingParam>(nucc_binaries, NuccBinaryType::PlayerSettingParam)?;
+    let costume_param = get_table::<CostumeParam>(nucc_binaries, NuccBinaryType::CostumeParam)?;
+    let character_select =
+        get_table::<CharacterSelectParam>(nucc_binaries, NuccBinaryType::CharacterSelectParam)?;
+    let player_icon = get_table::<PlayerIcon>(nucc_binaries, NuccBinaryType::PlayerIcon).ok();
+    let costume_break =
+        get_table::<CostumeBreakParam>(nucc_binaries, NuccBinaryType::CostumeBreakParam).ok();
+
+    let not_found = |nucc_type, description: String| {
+        CosprmError::entry_not_found(nucc_type, selector.to_string(), description)
+    };
+
+    let psp_entry = player_setting
+        .entries
+        .iter()
+        .filter(|entry| match selector {
+            CostumeSelector::Model {
+                characode,
+                model_index,
+            } => {
+                parse_searchcode(&entry.searchcode)
+                    .map_or(false, |(entry_characode, _)| entry_characode == characode)
+                    && entry.duel_player_param_model_index == *model_index
+            }
+            CostumeSelector::Searchcode(searchcode) => &entry.searchcode == searchcode,
+        })
+        .min_by_key(|entry| entry.player_setting_id)
+        .ok_or_else(|| {
+            not_found(
+                NuccBinaryType::PlayerSettingParam,
+                "no entry matches".to_string(),
+            )
+        })?;
+
+    let characode = parse_searchcode(&psp_entry.searchcode)
+        .map(|(characode, _)| characode.to_string())
+        .unwrap_or_else(|| psp_entry.searchcode.clone());
+    let model_index = psp_entry.duel_player_param_model_index;
+    let characode_index = psp_entry.characode_index as u32;
+
+    let mut colors = costume_param
+        .entries
+        .iter()
+        .filter(|entry| entry.player_setting_id == psp_entry.player_setting_id)
+        .collect::<Vec<_>>();
+    colors.sort_by_key(|entry| entry.color_index);
+
+    if colors.is_empty() {
+        return Err(not_found(
+            NuccBinaryType::CostumeParam,
+            format!(
+                "no colors with player_setting_id {}",
+                psp_entry.player_setting_id
+            ),
+        ));
+    }
+
+    let costume_id = character_select
+        .entries
+        .iter()
+        .filter(|entry| entry.searchcode == psp_entry.searchcode)
+        .min_by_key(|entry| entry.costume_slot_index)
+        .map(|entry| entry.costume_name.clone())
+        .ok_or_else(|| {
+            not_found(
+                NuccBinaryType::CharacterSelectParam,
+                format!("no entry with searchcode {}", psp_entry.searchcode),
+            )
+        })?;
+
+    let iconcode = player_icon
+        .iter()
+        .flat_map(|table| table.entries.iter())
+        .find(|entry| {
+            entry.characode_index as u32 == characode_index
+                && entry.duel_player_param_costume_index == model_index
+        })
+        .map(|entry| entry.icon_id.clone())
+        .unwrap_or_default();
+
+    let has_costume_break = costume_break
+        .iter()
+        .flat_map(|table| table.entries.iter())
+        .any(|entry| {
+            entry.characode_index as u32 == characode_index
+                && entry.costume_index == model_index as u32
+        });
+
+    // A price or unlock condition every color shares goes on the costume, otherwise on the colors
+    let prices = colors.iter().map(|entry| entry.price).collect::<Vec<_>>();
+    let unlock_conditions = colors
+        .iter()
+        .map(|entry| entry.unlock_condition)
+        .collect::<Vec<_>>();
+
+    let price = shared_value(&prices, DEFAULT_PRICE);
+    let unlock_condition = shared_value(&unlock_conditions, DEFAULT_UNLOCK_CONDITION);
+
+    let color_overrides = colors
+        .iter()
+        .map(|entry| ColorConfig {
+            color_index: entry.color_index,
+            price: price.is_none().then(|| entry.price),
+            unlock_condition: unlock_condition.is_none().then(|| entry.unlock_condition),
+        })
+        .filter(|color| {
+            color.price.map_or(false, |price| price != DEFAULT_PRICE)
+                || color.unlock_condition.map_or(false, |unlock_condition| {
+                    unlock_condition != DEFAULT_UNLOCK_CONDITION
+                })
+        })
+        .collect();
+
+    // Names in the main language, then the languages whose text differs
+    let language = language.unwrap_or(DEFAULT_LANGUAGE);
+    let message_infos = get_message_infos(nucc_binaries);
+
+    let main_message_info = message_infos
+        .iter()
+        .find(|(table_language, _)| table_language.as_deref() == Some(language))
+        .or_else(|| message_infos.first())
+        .map(|(_, message_info)| *message_info);
+
+    let char_name = main_message_info
+        .and_then(|message_info| message_text(message_info, &psp_entry.cha_b_id))
+        .unwrap_or_default();
+    let costume_name = main_message_info
+        .and_then(|message_info| message_text(message_info, &costume_id))
+        .unwrap_or_default();
+
+    let mut names = HashMap::new();
+
+    for (table_language, message_info) in &message_infos {
+        let table_language = match table_language {
+            Some(table_language) => table_language,
+            None => continue,
+        };
+
+        let localized = LocalizedNames {
+            char_name: message_text(message_info, &psp_entry.cha_b_id)
+                .filter(|text| *text != char_name),
+            costume_name: message_text(message_info, &costume_id)
+                .filter(|text| *text != costume_name),
+        };
+
+        if localized.char_name.is_some() || localized.costume_name.is_some() {
+            names.insert(table_language.clone(), localized);
+        }
+    }
+
+    Ok(CostumeConfig {
+        model_index,
+        characode,
+        modelcode: String::new(),
+        iconcode,
+        cha_id: psp_entry.cha_b_id.clone(),
+        char_name,
+        costume_id,
+        costume_name,
+        color_count: colors.len() as i32,
+        has_costume_break,
+        costume_break: None,
+        names,
+        price,
+        unlock_condition,
+        colors: color_overrides,
+        template: None,
+    })
+}
+
+fn message_text(message_info: &MessageInfo, id: &str) -> Option<String> {
+    let crc32 = calc_crc32(id);
+
+    message_info
+        .entries
+        .iter()
+        .find(|entry| entry.crc32 == crc32)
+        .map(|entry| entry.text3.clone())
+}
+
+/// The value every color shares, unless it's the default or the colors differ
+fn shared_value(values: &[u32], default: u32) -> Option<u32> {
+    let first = *values.first()?;
+
+    if values.iter().all(|value| *value == first) && first != default {
+        Some(first)
+    } else {
+        None
+    }
+}
@@ -0,0 +1,147 @@
+use crate::cfg::CostumeAddConfig;
+use crate::error::Result;
+use crate::lock::IdLock;
+use nuccbin::NuccBinaryType;
+
+use super::add_entry::*;
+use super::duel_player_param::DuelPlayerParamHandler;
+use super::nucc_binary_handler::NuccBinaries;
+use super::remove_entry::*;
+use super::report::ChangeReport;
+
+/// The tables every costume needs entries in, in the order they are added
+pub const REQUIRED_NUCC_TYPES: [NuccBinaryType; 6] = [
+    NuccBinaryType::MessageInfo,
+    NuccBinaryType::PlayerSettingParam,
+    NuccBinaryType::CostumeParam,
+    NuccBinaryType::PlayerIcon,
+    NuccBinaryType::CharacterSelectParam,
+    NuccBinaryType::CostumeBreakParam,
+];
+
+/// What a handler adds or removes the costumes of a config with
+pub struct HandlerContext<'a> {
+    pub cfg: &'a CostumeAddConfig,
+    pub options: &'a AddOptions,
+    pub lock: &'a mut IdLock,
+    pub report: &'a mut ChangeReport,
+}
+
+/// Adds and removes the rows of one table for the costumes of a config
+pub trait TableHandler {
+    fn nucc_type(&self) -> NuccBinaryType;
+
+    fn add(&self, nucc_binaries: &mut NuccBinaries, context: &mut HandlerContext) -> Result<()>;
+
+    fn remove(&self, nucc_binaries: &mut NuccBinaries, context: &mut HandlerContext) -> Result<()>;
+}
+
+/// The handlers a costume is installed with, in the order the tables are added.
+/// Tables are removed in the reverse order, so PlayerSettingParam is still there
+/// while the rows that are found through it are removed
+pub struct HandlerRegistry {
+    handlers: Vec<Box<dyn TableHandler>>,
+}
+
+impl Default for HandlerRegistry {
+    /// The six required tables and DuelPlayerParam
+    fn default() -> Self {
+        let mut registry = Self::empty();
+
+        for nucc_type in REQUIRED_NUCC_TYPES {
+            registry.register(RequiredTableHandler(nucc_type));
+        }
+        registry.register(DuelPlayerParamHandler);
+
+        registry
+    }
+}
+
+impl HandlerRegistry {
+    pub fn empty() -> Self {
+        Self {
+            handlers: Vec::new(),
+        }
+    }
+
+    /// Add a handler, replacing the one that was registered for its table
+    pub fn register(&mut self, handler: impl TableHandler + 'static) {
+        let nucc_type = handler.nucc_type();
+
+        match self
+            .handlers
+            .iter()
+            .position(|registered| registered.nucc_type() == nucc_type)
+        {
+            Some(index) => self.handlers[index] = Box::new(handler),
+            None => self.handlers.push(Box::new(handler)),
+        }
+    }
+
+    pub fn handlers(&self) -> impl DoubleEndedIterator<Item = &dyn TableHandler> {
+        self.handlers.iter().map(|handler| handler.as_ref())
+    }
+
+    /// The tables that have to be loaded for the handlers
+    pub fn nucc_types(&self) -> Vec<NuccBinaryType> {
+        self.handlers().map(|handler| handler.nucc_type()).collect()
+    }
+}
+
+/// The tables cosprm has always edited, through the add_entry and remove_entry functions
+struct RequiredTableHandler(NuccBinaryType);
+
+impl TableHandler for RequiredTableHandler {
+    fn nucc_type(&self) -> NuccBinaryType {
+        self.0
+    }
+
+    fn add(&self, nucc_binaries: &mut NuccBinaries, context: &mut HandlerContext) -> Result<()> {
+        let HandlerContext {
+            cfg,
+            options,
+            lock,
+            report,
+        } = context;
+
+        match self.0 {
+            NuccBinaryType::MessageInfo => {
+                add_message_info_entry(nucc_binaries, cfg, options, report)
+            }
+            NuccBinaryType::PlayerSettingParam => {
+                add_player_setting_entry(nucc_binaries, cfg, lock, report)
+            }
+            NuccBinaryType::CostumeParam => {
+                add_costume_entry(nucc_binaries, cfg, options, lock, report)
+            }
+            NuccBinaryType::PlayerIcon => add_icon_entry(nucc_binaries, cfg, options, report),
+            NuccBinaryType::CharacterSelectParam => {
+                add_character_select_entry(nucc_binaries, cfg, report)
+            }
+            NuccBinaryType::CostumeBreakParam => {
+                add_costume_break_entry(nucc_binaries, cfg, options, report)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn remove(&self, nucc_binaries: &mut NuccBinaries, context: &mut HandlerContext) -> Result<()> {
+        let HandlerContext { cfg, report, .. } = context;
+
+        match self.0 {
+            NuccBinaryType::MessageInfo => remove_message_info_entry(nucc_binaries, cfg, report),
+            NuccBinaryType::PlayerSettingParam => {
+                remove_player_setting_entry(nucc_binaries, cfg, report)
+            }
+            NuccBinaryType::CostumeParam => remove_costume_entry(nucc_binaries, cfg, report),
+            NuccBinaryType::PlayerIcon => remove_icon_entry(nucc_binaries, cfg, report),
+            NuccBinaryType::CharacterSelectParam => {
+                remove_character_select_entry(nucc_binaries, cfg, report)
+            }
+            NuccBinaryType::CostumeBreakParam => {
+                remove_costume_break_entry(nucc_binaries, cfg, report)
+            }
+            _ => Ok(()),
+        }
+    }
+}
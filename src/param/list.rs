@@ -0,0 +1,283 @@
+use crate::error::Result;
+use nuccbin::{
+    nucc_binary::{
+        CharacterSelectParam, CostumeBreakParam, CostumeParam, PlayerIcon, PlayerSettingParam,
+    },
+    NuccBinaryType,
+};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+use super::calc_crc32;
+use super::message_dictionary::{format_crc32, MessageDictionary};
+use super::nucc_binary_handler::{get_message_info, get_table, NuccBinaries};
+use super::report::*;
+use super::searchcode::parse_searchcode;
+
+/// The MessageInfo language names are resolved from unless another one is asked for
+pub const DEFAULT_LANGUAGE: &str = "eng";
+
+/// Every costume of a character
+#[derive(Debug, Serialize)]
+pub struct CharacterListing {
+    pub characode: String,
+    pub costumes: Vec<CostumeListing>,
+}
+
+/// A PlayerSettingParam row and the rows of the other tables that belong to it
+#[derive(Debug, Serialize)]
+pub struct CostumeListing {
+    pub player_setting: PlayerSettingRow,
+    pub colors: Vec<CostumeParamRow>,
+    pub icons: Vec<PlayerIconRow>,
+    pub character_select: Vec<CharacterSelectRow>,
+    pub costume_break: Vec<CostumeBreakRow>,
+    /// MessageInfo text of the message ids the rows use
+    pub names: BTreeMap<String, String>,
+}
+
+/// A MessageInfo row with the id its crc32 belongs to, when that id is known
+#[derive(Debug, Serialize)]
+pub struct MessageListing {
+    pub crc32: String,
+    pub id: Option<String>,
+    pub text: String,
+}
+
+/// Group the PlayerSettingParam rows by characode, optionally only the ones of a single characode.
+/// Tables other than PlayerSettingParam are optional and left out when they weren't loaded
+pub fn list_costumes(
+    nucc_binaries: &NuccBinaries,
+    characode: Option<&str>,
+    language: Option<&str>,
+) -> Result<Vec<CharacterListing>> {
+    let player_setting =
+        get_table::<PlayerSettingParam>(nucc_binaries, NuccBinaryType::PlayerSettingParam)?;
+    let costume_param = get_table::<CostumeParam>(nucc_binaries, NuccBinaryType::CostumeParam).ok();
+    let player_icon = get_table::<PlayerIcon>(nucc_binaries, NuccBinaryType::PlayerIcon).ok();
+    let character_select =
+        get_table::<CharacterSelectParam>(nucc_binaries, NuccBinaryType::CharacterSelectParam).ok();
+    let costume_break =
+        get_table::<CostumeBreakParam>(nucc_binaries, NuccBinaryType::CostumeBreakParam).ok();
+    let message_info = get_message_info(nucc_binaries, language.unwrap_or(DEFAULT_LANGUAGE));
+
+    let mut characters: BTreeMap<String, Vec<CostumeListing>> = BTreeMap::new();
+
+    let mut psp_entries = player_setting.entries.iter().collect::<Vec<_>>();
+    psp_entries.sort_by_key(|entry| entry.player_setting_id);
+
+    for psp_entry in psp_entries {
+        let entry_characode = parse_searchcode(&psp_entry.searchcode)
+            .map(|(characode, _)| characode)
+            .unwrap_or(psp_entry.searchcode.as_str());
+
+        if characode.map_or(false, |characode| entry_characode != characode) {
+            continue;
+        }
+
+        let characode_index = psp_entry.characode_index as u32;
+        let model_index = psp_entry.duel_player_param_model_index;
+
+        let colors = costume_param
+            .iter()
+            .flat_map(|table| table.entries.iter())
+            .filter(|entry| entry.player_setting_id == psp_entry.player_setting_id)
+            .map(|entry| CostumeParamRow {
+                player_setting_id: entry.player_setting_id as u32,
+                color_index: entry.color_index,
+                costume_name: entry.costume_name.clone(),
+                costume_link: entry.costume_link.clone(),
+                price: entry.price,
+                unlock_condition: entry.unlock_condition,
+            })
+            .collect::<Vec<_>>();
+
+        let icons = player_icon
+            .iter()
+            .flat_map(|table| table.entries.iter())
+            .filter(|entry| {
+                entry.characode_index as u32 == characode_index
+                    && entry.duel_player_param_costume_index == model_index
+            })
+            .map(|entry| PlayerIconRow {
+                characode_index: entry.characode_index as u32,
+                costume_index: entry.duel_player_param_costume_index,
+                icon_id: entry.icon_id.clone(),
+            })
+            .collect();
+
+        let character_select_rows = character_select
+            .iter()
+            .flat_map(|table| table.entries.iter())
+            .filter(|entry| entry.searchcode == psp_entry.searchcode)
+            .map(|entry| CharacterSelectRow {
+                page_index: entry.page_index as u32,
+                slot_index: entry.slot_index as u32,
+                costume_slot_index: entry.costume_slot_index,
+                searchcode: entry.searchcode.clone(),
+                costume_name: entry.costume_name.clone(),
+            })
+            .collect::<Vec<_>>();
+
+        let costume_break_rows = costume_break
+            .iter()
+            .flat_map(|table| table.entries.iter())
+            .filter(|entry| {
+                entry.characode_index as u32 == characode_index
+                    && entry.costume_index == model_index as u32
+            })
+            .map(|entry| CostumeBreakRow {
+                characode_index: entry.characode_index as u32,
+                costume_index: entry.costume_index,
+            })
+            .collect();
+
+        let message_ids = std::iter::once(&psp_entry.cha_b_id)
+            .chain(colors.iter().map(|row| &row.costume_name))
+            .chain(character_select_rows.iter().map(|row| &row.costume_name));
+
+        let mut names = BTreeMap::new();
+        if let Some(message_info) = message_info {
+            for id in message_ids {
+                let crc32 = calc_crc32(id);
+                if let Some(entry) = message_info
+                    .entries
+                    .iter()
+                    .find(|entry| entry.crc32 == crc32)
+                {
+                    names.insert(id.clone(), entry.text3.clone());
+                }
+            }
+        }
+
+        characters
+            .entry(entry_characode.to_string())
+            .or_default()
+            .push(CostumeListing {
+                player_setting: PlayerSettingRow {
+                    player_setting_id: psp_entry.player_setting_id as u32,
+                    searchcode: psp_entry.searchcode.clone(),
+                    cha_b_id: psp_entry.cha_b_id.clone(),
+                    characode_index,
+                    model_index,
+                },
+                colors,
+                icons,
+                character_select: character_select_rows,
+                costume_break: costume_break_rows,
+                names,
+            });
+    }
+
+    Ok(characters
+        .into_iter()
+        .map(|(characode, costumes)| CharacterListing {
+            characode,
+            costumes,
+        })
+        .collect())
+}
+
+/// The rows of the MessageInfo table of a language, with their ids looked up in the dictionary
+pub fn list_messages(
+    nucc_binaries: &NuccBinaries,
+    message_ids: &MessageDictionary,
+    language: Option<&str>,
+) -> Vec<MessageListing> {
+    get_message_info(nucc_binaries, language.unwrap_or(DEFAULT_LANGUAGE))
+        .map(|message_info| {
+            message_info
+                .entries
+                .iter()
+                .map(|entry| MessageListing {
+                    crc32: format_crc32(&entry.crc32),
+                    id: message_ids.lookup(&entry.crc32).map(str::to_string),
+                    text: entry.text3.clone(),
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+pub fn print_messages(messages: &[MessageListing]) {
+    if messages.is_empty() {
+        println!("No messages found.");
+        return;
+    }
+
+    for message in messages {
+        println!(
+            "{} {} \"{}\"",
+            message.crc32,
+            message.id.as_deref().unwrap_or("?"),
+            message.text
+        );
+    }
+}
+
+/// Print the listing grouped per character, one costume at a time
+pub fn print_listing(characters: &[CharacterListing]) {
+    if characters.is_empty() {
+        println!("No costumes found.");
+        return;
+    }
+
+    for character in characters {
+        println!(
+            "{} ({} costumes):",
+            character.characode,
+            character.costumes.len()
+        );
+
+        for costume in &character.costumes {
+            let row = &costume.player_setting;
+            println!(
+                "    id {} searchcode {} cha_b_id {}{} characode_index {} model_index {}",
+                row.player_setting_id,
+                row.searchcode,
+                row.cha_b_id,
+                name_suffix(&costume.names, &row.cha_b_id),
+                row.characode_index,
+                row.model_index
+            );
+
+            for color in &costume.colors {
+                println!(
+                    "        color {} {} {}{} price {} unlock_condition {}",
+                    color.color_index,
+                    color.costume_link,
+                    color.costume_name,
+                    name_suffix(&costume.names, &color.costume_name),
+                    color.price,
+                    color.unlock_condition
+                );
+            }
+
+            for icon in &costume.icons {
+                println!("        icon {}", icon.icon_id);
+            }
+
+            for slot in &costume.character_select {
+                println!(
+                    "        select page {} slot {} costume slot {} {}{}",
+                    slot.page_index,
+                    slot.slot_index,
+                    slot.costume_slot_index,
+                    slot.costume_name,
+                    name_suffix(&costume.names, &slot.costume_name)
+                );
+            }
+
+            if !costume.costume_break.is_empty() {
+                println!("        costume break");
+            }
+        }
+    }
+}
+
+fn name_suffix(names: &BTreeMap<String, String>, id: &str) -> String {
+    names
+        .get(id)
+        .map(|name| format!(" \"{}\"", name))
+        .unwrap_or_default()
+}
@@ -0,0 +1,134 @@
+use crate::error::{CosprmError, Result};
+use nuccbin::{
+    nucc_binary::{CharacterSelectParam, CostumeParam, PlayerSettingParam},
+    NuccBinaryType,
+};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use super::calc_crc32;
+use super::message_id::{CHA_ID_PREFIX, COSTUME_ID_PREFIX};
+use super::nucc_binary_handler::{get_table, NuccBinaries};
+
+/// Highest number of the generated c_cha_NNN and c_costume_NNN ids
+const MAX_NUMBERED_ID: u32 = 999;
+
+/// Message ids by their crc32. MessageInfo only stores the crc32 of an id, so this is the only
+/// way to tell which id a row belongs to. It only names the ids it was given, most game messages
+/// stay unknown
+#[derive(Debug, Clone, Default)]
+pub struct MessageDictionary {
+    ids: HashMap<[u8; 4], String>,
+}
+
+impl MessageDictionary {
+    /// Generated c_cha_NNN and c_costume_NNN ids with three digits. They follow the pattern of
+    /// costume names, whether or not the game uses them
+    pub fn builtin() -> Self {
+        let mut dictionary = Self::default();
+
+        for prefix in [CHA_ID_PREFIX, COSTUME_ID_PREFIX] {
+            for number in 0..=MAX_NUMBERED_ID {
+                dictionary.insert(&format!("{}{:03}", prefix, number));
+            }
+        }
+
+        dictionary
+    }
+
+    /// The generated ids along with every message id the tables reference
+    pub fn for_tables(nucc_binaries: &NuccBinaries) -> Self {
+        let mut dictionary = Self::builtin();
+
+        if let Ok(player_setting) =
+            get_table::<PlayerSettingParam>(nucc_binaries, NuccBinaryType::PlayerSettingParam)
+        {
+            for entry in &player_setting.entries {
+                dictionary.insert(&entry.cha_b_id);
+            }
+        }
+
+        if let Ok(costume_param) =
+            get_table::<CostumeParam>(nucc_binaries, NuccBinaryType::CostumeParam)
+        {
+            for entry in &costume_param.entries {
+                dictionary.insert(&entry.costume_name);
+            }
+        }
+
+        if let Ok(character_select) =
+            get_table::<CharacterSelectParam>(nucc_binaries, NuccBinaryType::CharacterSelectParam)
+        {
+            for entry in &character_select.entries {
+                dictionary.insert(&entry.costume_name);
+            }
+        }
+
+        dictionary
+    }
+
+    /// Add the ids of a text file, one per line. Empty lines and lines starting with # are skipped
+    pub fn load_file(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+
+        let text = fs::read_to_string(path).map_err(|source| CosprmError::ConfigRead {
+            path: path.display().to_string(),
+            source,
+        })?;
+
+        for line in text.lines().map(str::trim) {
+            if !line.is_empty() && !line.starts_with('#') {
+                self.insert(line);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Add an id. When another id with the same crc32 is already known, that one is kept
+    /// and returned
+    pub fn insert(&mut self, id: &str) -> Option<&str> {
+        if id.is_empty() {
+            return None;
+        }
+
+        let known: &String = self
+            .ids
+            .entry(calc_crc32(id))
+            .or_insert_with(|| id.to_string());
+
+        (known.as_str() != id).then(|| known.as_str())
+    }
+
+    pub fn lookup(&self, crc32: &[u8; 4]) -> Option<&str> {
+        self.ids.get(crc32).map(String::as_str)
+    }
+
+    /// The known id that has the same crc32 as `id` but isn't `id`
+    pub fn collision(&self, id: &str) -> Option<&str> {
+        if id.is_empty() {
+            return None;
+        }
+
+        self.lookup(&calc_crc32(id)).filter(|known| *known != id)
+    }
+
+    /// The id of a crc32 if it's known, otherwise the crc32 in hex
+    pub fn describe(&self, crc32: &[u8; 4]) -> String {
+        self.lookup(crc32)
+            .map(str::to_string)
+            .unwrap_or_else(|| format_crc32(crc32))
+    }
+}
+
+/// A crc32 as it's shown to users, e.g. "0x1a2b3c4d"
+pub fn format_crc32(crc32: &[u8; 4]) -> String {
+    format!(
+        "0x{}",
+        crc32
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect::<String>()
+    )
+}
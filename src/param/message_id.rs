@@ -0,0 +1,82 @@
+use nuccbin::{
+    nucc_binary::{CharacterSelectParam, CostumeParam, PlayerSettingParam},
+    NuccBinaryType,
+};
+use std::collections::HashSet;
+
+use super::calc_crc32;
+use super::nucc_binary_handler::{get_message_infos, get_table, NuccBinaries};
+
+/// Character name ids look like c_cha_012
+pub const CHA_ID_PREFIX: &str = "c_cha_";
+/// Costume name ids look like c_costume_012
+pub const COSTUME_ID_PREFIX: &str = "c_costume_";
+
+/// The number of a message id like c_cha_012, if it has the prefix
+pub fn parse_message_id(id: &str, prefix: &str) -> Option<u32> {
+    let number = id.strip_prefix(prefix)?;
+
+    if number.is_empty() || !number.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+
+    number.parse().ok()
+}
+
+/// The next unused message id with a prefix, one past the highest number used by the
+/// PlayerSettingParam cha_b_ids and the CostumeParam and CharacterSelectParam costume names.
+/// Reserved ids are treated as used, and ids whose crc32 already has MessageInfo text are skipped
+pub fn next_message_id(nucc_binaries: &NuccBinaries, prefix: &str, reserved: &[&str]) -> String {
+    let mut ids = reserved.to_vec();
+
+    if let Ok(player_setting) =
+        get_table::<PlayerSettingParam>(nucc_binaries, NuccBinaryType::PlayerSettingParam)
+    {
+        ids.extend(
+            player_setting
+                .entries
+                .iter()
+                .map(|entry| entry.cha_b_id.as_str()),
+        );
+    }
+
+    if let Ok(costume_param) =
+        get_table::<CostumeParam>(nucc_binaries, NuccBinaryType::CostumeParam)
+    {
+        ids.extend(
+            costume_param
+                .entries
+                .iter()
+                .map(|entry| entry.costume_name.as_str()),
+        );
+    }
+
+    if let Ok(character_select) =
+        get_table::<CharacterSelectParam>(nucc_binaries, NuccBinaryType::CharacterSelectParam)
+    {
+        ids.extend(
+            character_select
+                .entries
+                .iter()
+                .map(|entry| entry.costume_name.as_str()),
+        );
+    }
+
+    let mut next = ids
+        .iter()
+        .filter_map(|id| parse_message_id(id, prefix))
+        .max()
+        .map_or(1, |number| number + 1);
+
+    // Message ids only show up in MessageInfo as crc32s, so they can't be scanned for a maximum
+    let message_crc32s = get_message_infos(nucc_binaries)
+        .into_iter()
+        .flat_map(|(_, message_info)| message_info.entries.iter().map(|entry| entry.crc32))
+        .collect::<HashSet<_>>();
+
+    while message_crc32s.contains(&calc_crc32(&format!("{}{:03}", prefix, next))) {
+        next += 1;
+    }
+
+    format!("{}{:03}", prefix, next)
+}
@@ -1,7 +1,33 @@
 pub mod add_entry;
 
+pub mod check;
+
+pub mod duel_player_param;
+
+pub mod extract;
+
+pub mod handler;
+
+pub mod list;
+
+pub mod message_dictionary;
+
+pub mod message_id;
+
+pub mod model_index;
+
 pub mod nucc_binary_handler;
 
+pub mod patch;
+
+pub mod remove_entry;
+
+pub mod report;
+
+pub mod searchcode;
+
+pub mod table_file;
+
 use crc::{Crc, CRC_32_BZIP2};
 
 pub fn calc_crc32(data: &str) -> [u8; 4] {
@@ -0,0 +1,72 @@
+use nuccbin::{
+    nucc_binary::{CharacterSelectParam, DuelPlayerParam, PlayerSettingParam},
+    NuccBinaryType,
+};
+
+use super::nucc_binary_handler::{get_table, NuccBinaries};
+use super::searchcode::parse_searchcode;
+
+/// The next unused model index of a characode, one past the highest one used by its
+/// PlayerSettingParam entries and CharacterSelectParam costume slots. Reserved indexes are
+/// treated as used. Never 0, since that's the character's base costume.
+///
+/// The costume list of the character's DuelPlayerParam entry has a fixed number of slots, so when
+/// it's loaded the lowest unused index with an empty slot is picked instead. If every slot is
+/// taken, the index past the list is returned and adding the costume fails on the missing slot
+pub fn next_model_index(nucc_binaries: &NuccBinaries, characode: &str, reserved: &[i32]) -> i32 {
+    let mut model_indexes = reserved.to_vec();
+
+    let is_character = |searchcode: &str| {
+        parse_searchcode(searchcode)
+            .map_or(false, |(entry_characode, _)| entry_characode == characode)
+    };
+
+    if let Ok(player_setting) =
+        get_table::<PlayerSettingParam>(nucc_binaries, NuccBinaryType::PlayerSettingParam)
+    {
+        model_indexes.extend(
+            player_setting
+                .entries
+                .iter()
+                .filter(|entry| is_character(&entry.searchcode))
+                .map(|entry| entry.duel_player_param_model_index),
+        );
+    }
+
+    if let Ok(character_select) =
+        get_table::<CharacterSelectParam>(nucc_binaries, NuccBinaryType::CharacterSelectParam)
+    {
+        model_indexes.extend(
+            character_select
+                .entries
+                .iter()
+                .filter(|entry| is_character(&entry.searchcode))
+                .map(|entry| entry.costume_slot_index as i32),
+        );
+    }
+
+    let past_highest = model_indexes
+        .iter()
+        .max()
+        .map_or(1, |model_index| model_index + 1)
+        .max(1);
+
+    let slots = get_table::<DuelPlayerParam>(nucc_binaries, NuccBinaryType::DuelPlayerParam)
+        .ok()
+        .and_then(|duel_player| {
+            duel_player
+                .entries
+                .iter()
+                .find(|entry| entry.characode == characode)
+                .map(|entry| &entry.costumes)
+        });
+
+    match slots {
+        Some(slots) => (1..slots.len() as i32)
+            .find(|model_index| {
+                !model_indexes.contains(model_index) && slots[*model_index as usize].is_empty()
+            })
+            .unwrap_or_else(|| past_highest.max(slots.len() as i32)),
+        None => past_highest,
+    }
+}
@@ -1,98 +1,392 @@
+use crate::backup::BACKUP_DIR;
+use crate::error::{CosprmError, Result};
 use nuccbin::nucc_binary::{
-    NuccBinaryParsed, NuccBinaryParsedDeserializer, NuccBinaryParsedReader, NuccBinaryParsedWriter,
+    MessageInfo, NuccBinaryParsed, NuccBinaryParsedDeserializer, NuccBinaryParsedReader,
+    NuccBinaryParsedWriter,
 };
 use nuccbin::NuccBinaryType;
 use std::collections::HashMap;
-use std::path::Path;
+use std::fs;
+use std::path::{Path, PathBuf};
 use strum::IntoEnumIterator;
 use walkdir::WalkDir;
 use xfbin::{nucc::NuccChunk, read_xfbin, write_xfbin};
 
-const NUCC_BINARY_PATTERNS: [NuccBinaryType; 6] = [
+/// The tables that are loaded unless others are asked for, the ones the default handlers edit
+pub const DEFAULT_NUCC_TYPES: [NuccBinaryType; 7] = [
     NuccBinaryType::MessageInfo,
     NuccBinaryType::PlayerSettingParam,
     NuccBinaryType::CostumeParam,
     NuccBinaryType::PlayerIcon,
     NuccBinaryType::CharacterSelectParam,
     NuccBinaryType::CostumeBreakParam,
+    NuccBinaryType::DuelPlayerParam,
 ];
 
+/// Identifies a parsed NUCC binary by the xfbin it was read from and its chunk path,
+/// so tables that exist once per language (e.g. MessageInfo) don't overwrite each other
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct NuccBinaryKey {
+    pub filepath: String,
+    pub chunk_filepath: String,
+    pub nucc_type: NuccBinaryType,
+}
+
+impl NuccBinaryKey {
+    /// The language folder the chunk lives in (e.g. "eng" for "WIN64/eng/messageInfo.bin")
+    pub fn language(&self) -> Option<String> {
+        Path::new(&self.chunk_filepath)
+            .parent()
+            .and_then(|parent| parent.file_name())
+            .or_else(|| {
+                Path::new(&self.filepath)
+                    .parent()
+                    .and_then(|parent| parent.file_name())
+            })
+            .map(|name| name.to_string_lossy().to_string())
+    }
+}
+
+/// A parsed NUCC binary along with whether it was modified since it was loaded
+pub struct LoadedNuccBinary {
+    pub parsed: Box<dyn NuccBinaryParsed>,
+    pub dirty: bool,
+}
+
+impl LoadedNuccBinary {
+    pub fn new(parsed: Box<dyn NuccBinaryParsed>) -> Self {
+        Self {
+            parsed,
+            dirty: false,
+        }
+    }
+
+    pub fn mark_dirty(&mut self) {
+        self.dirty = true;
+    }
+
+    /// Downcast the parsed binary to its table
+    pub fn table_mut<T: NuccBinaryParsed>(&mut self, nucc_type: NuccBinaryType) -> Result<&mut T> {
+        self.parsed
+            .downcast_mut::<T>()
+            .ok_or(CosprmError::MissingTable { nucc_type })
+    }
+}
+
+pub type NuccBinaries = HashMap<NuccBinaryKey, LoadedNuccBinary>;
+
+/// Get the first loaded NUCC binary of a type, for tables that only exist once
+pub fn get_nucc_binary<'a>(
+    nucc_binaries: &'a NuccBinaries,
+    nucc_type: NuccBinaryType,
+) -> Option<&'a LoadedNuccBinary> {
+    nucc_binaries
+        .iter()
+        .filter(|(key, _)| key.nucc_type == nucc_type)
+        .min_by(|(a, _), (b, _)| a.filepath.cmp(&b.filepath))
+        .map(|(_, nucc_binary)| nucc_binary)
+}
+
+/// Get the first loaded NUCC binary of a type downcast to its table
+pub fn get_table<T: NuccBinaryParsed>(
+    nucc_binaries: &NuccBinaries,
+    nucc_type: NuccBinaryType,
+) -> Result<&T> {
+    get_nucc_binary(nucc_binaries, nucc_type)
+        .and_then(|nucc_binary| nucc_binary.parsed.downcast_ref::<T>())
+        .ok_or(CosprmError::MissingTable { nucc_type })
+}
+
+/// Mutable version of `get_nucc_binary`
+pub fn get_nucc_binary_mut<'a>(
+    nucc_binaries: &'a mut NuccBinaries,
+    nucc_type: NuccBinaryType,
+) -> Option<&'a mut LoadedNuccBinary> {
+    let key = nucc_binaries
+        .keys()
+        .filter(|key| key.nucc_type == nucc_type)
+        .min_by(|a, b| a.filepath.cmp(&b.filepath))
+        .cloned()?;
+
+    nucc_binaries.get_mut(&key)
+}
+
+/// Get every loaded NUCC binary of a type, sorted by file path
+pub fn get_nucc_binaries_of_type_mut(
+    nucc_binaries: &mut NuccBinaries,
+    nucc_type: NuccBinaryType,
+) -> Vec<(&NuccBinaryKey, &mut LoadedNuccBinary)> {
+    let mut matching = nucc_binaries
+        .iter_mut()
+        .filter(|(key, _)| key.nucc_type == nucc_type)
+        .collect::<Vec<_>>();
+
+    matching.sort_by(|(a, _), (b, _)| a.filepath.cmp(&b.filepath));
+    matching
+}
+
+/// Every loaded MessageInfo table along with its language, sorted by file path
+pub fn get_message_infos(nucc_binaries: &NuccBinaries) -> Vec<(Option<String>, &MessageInfo)> {
+    let mut tables = nucc_binaries
+        .iter()
+        .filter(|(key, _)| key.nucc_type == NuccBinaryType::MessageInfo)
+        .filter_map(|(key, nucc_binary)| {
+            nucc_binary
+                .parsed
+                .downcast_ref::<MessageInfo>()
+                .map(|message_info| (key, message_info))
+        })
+        .collect::<Vec<_>>();
+    tables.sort_by(|(a, _), (b, _)| a.filepath.cmp(&b.filepath));
+
+    tables
+        .into_iter()
+        .map(|(key, message_info)| (key.language(), message_info))
+        .collect()
+}
+
+/// The MessageInfo table of a language, or the first one when that language wasn't loaded
+pub fn get_message_info<'a>(
+    nucc_binaries: &'a NuccBinaries,
+    language: &str,
+) -> Option<&'a MessageInfo> {
+    let tables = get_message_infos(nucc_binaries);
+
+    tables
+        .iter()
+        .find(|(table_language, _)| table_language.as_deref() == Some(language))
+        .or_else(|| tables.first())
+        .map(|(_, message_info)| *message_info)
+}
+
+/// Check if at least one NUCC binary of a type was loaded
+pub fn has_nucc_binary(nucc_binaries: &NuccBinaries, nucc_type: NuccBinaryType) -> bool {
+    nucc_binaries.keys().any(|key| key.nucc_type == nucc_type)
+}
+
 //// Gather parsed NUCC binaries from a directory
-pub fn get_nucc_binaries(directory: &Path) -> HashMap<NuccBinaryType, Box<dyn NuccBinaryParsed>> {
+pub fn get_nucc_binaries(directory: &Path) -> Result<NuccBinaries> {
+    get_nucc_binaries_of_types(directory, &DEFAULT_NUCC_TYPES)
+}
+
+/// Gather the parsed NUCC binaries of some types from a directory
+pub fn get_nucc_binaries_of_types(
+    directory: &Path,
+    nucc_types: &[NuccBinaryType],
+) -> Result<NuccBinaries> {
     let mut nucc_type_parsed = HashMap::new();
 
     let files = collect_files(&directory);
 
-    files.iter().for_each(|file| {
-        let xfbin = read_xfbin(Path::new(file)).unwrap();
+    for file in &files {
+        let xfbin = read_xfbin(Path::new(file)).map_err(|e| CosprmError::XfbinRead {
+            path: file.clone(),
+            message: e.to_string(),
+        })?;
         for chunk in xfbin.get_chunks_by_type("nuccChunkBinary") {
             let chunk_info = &xfbin.chunk_table.get_chunk_info(&chunk.chunk_map);
 
             if let Some(nucc_binary_type) = find_nucc_binary_type(&chunk_info.1) {
                 let nucc_binary = chunk.data.as_bytes();
 
-                if NUCC_BINARY_PATTERNS.contains(&nucc_binary_type) {
+                if nucc_types.contains(&nucc_binary_type) {
                     let reader = NuccBinaryParsedReader(nucc_binary_type, &nucc_binary);
                     let nucc_binary_parsed: Box<dyn NuccBinaryParsed> = reader.into();
-                    nucc_type_parsed.insert(nucc_binary_type, nucc_binary_parsed);
+
+                    let key = NuccBinaryKey {
+                        filepath: file.clone(),
+                        chunk_filepath: chunk_info.1.clone(),
+                        nucc_type: nucc_binary_type,
+                    };
+                    nucc_type_parsed.insert(key, LoadedNuccBinary::new(nucc_binary_parsed));
                 }
             }
         }
-    });
+    }
 
     if nucc_type_parsed.is_empty() {
-        panic!("No valid NUCC binaries found in the directory!");
+        return Err(CosprmError::NoNuccBinaries {
+            directory: directory.display().to_string(),
+        });
     }
 
-    nucc_type_parsed
+    Ok(nucc_type_parsed)
 }
 
-pub fn save_nucc_binaries(
-    directory: &Path,
-    nucc_binaries: &mut HashMap<NuccBinaryType, Box<dyn NuccBinaryParsed>>,
-) {
-    let files = collect_files(&directory);
+/// Write the NUCC binaries that were modified back to the xfbins they were read from,
+/// returning the paths of the files that were written.
+///
+/// Every file is staged next to the original and verified before any of them are replaced,
+/// so either all of the files are updated or none of them are
+pub fn save_nucc_binaries(nucc_binaries: &mut NuccBinaries) -> Result<Vec<String>> {
+    let files = dirty_files(nucc_binaries);
+
+    let mut staged_files = Vec::new();
 
     for file in &files {
-        let mut xfbin = read_xfbin(Path::new(file)).unwrap();
+        let staged_file = with_suffix(file, STAGED_SUFFIX);
 
-        let mut updated_chunks = Vec::new();
+        if let Err(e) = stage_xfbin(file, &staged_file, nucc_binaries) {
+            staged_files.push(staged_file);
+            discard_files(&staged_files);
+            return Err(e);
+        }
 
-        for chunk in xfbin.get_chunks_by_type("nuccChunkBinary") {
-            let chunk_info = &xfbin.chunk_table.get_chunk_info(&chunk.chunk_map);
+        staged_files.push(staged_file);
+    }
 
-            if let Some(nucc_binary_type) = find_nucc_binary_type(&chunk_info.1) {
-                if let Some(nucc_binary) = nucc_binaries.get_mut(&nucc_binary_type) {
-                    if NUCC_BINARY_PATTERNS.contains(&nucc_binary_type) {
-                        let deserializer =
-                            NuccBinaryParsedDeserializer(nucc_binary_type, nucc_binary.serialize());
-                        let writer = NuccBinaryParsedWriter(deserializer.into());
-                        let bytes: Vec<u8> = writer.into();
-
-                        // Replace the chunk data with the serialized binary chunk
-                        let mut updated_chunk = chunk.clone();
-                        updated_chunk.size = bytes.len() as u32;
-                        updated_chunk.data = NuccChunk::NuccBinary(bytes.clone());
-
-                        // Replace the chunk in the xfbin
-                        updated_chunks.push(updated_chunk);
-                    }
+    commit_staged_files(&files, &staged_files)?;
+
+    // Everything that was dirty is now on disk
+    nucc_binaries
+        .values_mut()
+        .for_each(|nucc_binary| nucc_binary.dirty = false);
+
+    Ok(files)
+}
+
+/// The xfbins that contain at least one modified NUCC binary, sorted by path
+pub fn dirty_files(nucc_binaries: &NuccBinaries) -> Vec<String> {
+    let mut files = nucc_binaries
+        .iter()
+        .filter(|(_, nucc_binary)| nucc_binary.dirty)
+        .map(|(key, _)| key.filepath.clone())
+        .collect::<Vec<String>>();
+
+    files.sort();
+    files.dedup();
+    files
+}
+
+const STAGED_SUFFIX: &str = ".cosprm-staged";
+const ORIGINAL_SUFFIX: &str = ".cosprm-original";
+
+/// Write a copy of the xfbin with the modified chunks to `staged_file` and make sure it reads back
+fn stage_xfbin(file: &str, staged_file: &Path, nucc_binaries: &NuccBinaries) -> Result<()> {
+    let mut xfbin = read_xfbin(Path::new(file)).map_err(|e| CosprmError::XfbinRead {
+        path: file.to_string(),
+        message: e.to_string(),
+    })?;
+
+    let mut updated_chunks = Vec::new();
+
+    for chunk in xfbin.get_chunks_by_type("nuccChunkBinary") {
+        let chunk_info = &xfbin.chunk_table.get_chunk_info(&chunk.chunk_map);
+
+        if let Some(nucc_binary_type) = find_nucc_binary_type(&chunk_info.1) {
+            let key = NuccBinaryKey {
+                filepath: file.to_string(),
+                chunk_filepath: chunk_info.1.clone(),
+                nucc_type: nucc_binary_type,
+            };
+
+            if let Some(nucc_binary) = nucc_binaries.get(&key) {
+                // Only the types that were loaded are in the map
+                if nucc_binary.dirty {
+                    let deserializer = NuccBinaryParsedDeserializer(
+                        nucc_binary_type,
+                        nucc_binary.parsed.serialize(),
+                    );
+                    let writer = NuccBinaryParsedWriter(deserializer.into());
+                    let bytes: Vec<u8> = writer.into();
+
+                    // Replace the chunk data with the serialized binary chunk
+                    let mut updated_chunk = chunk.clone();
+                    updated_chunk.size = bytes.len() as u32;
+                    updated_chunk.data = NuccChunk::NuccBinary(bytes.clone());
+
+                    // Replace the chunk in the xfbin
+                    updated_chunks.push(updated_chunk);
                 }
             }
         }
+    }
 
-        for u in updated_chunks {
-            for page in &mut xfbin.pages {
-                for chunk in &mut page.chunks {
-                    if chunk.chunk_map == u.chunk_map {
-                        *chunk = u.clone();
-                    }
+    for u in &updated_chunks {
+        for page in &mut xfbin.pages {
+            for chunk in &mut page.chunks {
+                if chunk.chunk_map == u.chunk_map {
+                    *chunk = u.clone();
                 }
             }
         }
+    }
+
+    write_xfbin(staged_file, &mut xfbin).map_err(|e| CosprmError::XfbinWrite {
+        path: staged_file.display().to_string(),
+        message: e.to_string(),
+    })?;
+
+    // Verify the staged file is a valid xfbin whose chunks read back as what we wrote
+    let staged = read_xfbin(staged_file).map_err(|e| CosprmError::XfbinWrite {
+        path: staged_file.display().to_string(),
+        message: format!("staged file could not be read back: {}", e),
+    })?;
+
+    let staged_chunks = staged.get_chunks_by_type("nuccChunkBinary");
 
-        write_xfbin(Path::new(file), &mut xfbin).unwrap();
+    for u in &updated_chunks {
+        let matches = staged_chunks
+            .iter()
+            .find(|chunk| chunk.chunk_map == u.chunk_map)
+            .map_or(false, |chunk| chunk.data.as_bytes() == u.data.as_bytes());
+
+        if !matches {
+            let chunk_info = xfbin.chunk_table.get_chunk_info(&u.chunk_map);
+
+            return Err(CosprmError::XfbinWrite {
+                path: staged_file.display().to_string(),
+                message: format!("{} did not read back as it was written", chunk_info.1),
+            });
+        }
     }
+
+    Ok(())
+}
+
+/// Swap every staged file into place. If any of them fails, the originals that were
+/// already replaced are put back and the staged files are removed
+fn commit_staged_files(files: &[String], staged_files: &[PathBuf]) -> Result<()> {
+    let mut committed = Vec::new();
+
+    for (file, staged_file) in files.iter().zip(staged_files) {
+        let original_file = with_suffix(file, ORIGINAL_SUFFIX);
+
+        let swapped = fs::rename(file, &original_file).and_then(|_| {
+            fs::rename(staged_file, file).map_err(|e| {
+                // Put this file's original back before reporting the error
+                let _ = fs::rename(&original_file, file);
+                e
+            })
+        });
+
+        if let Err(source) = swapped {
+            for (file, original_file) in committed.iter().rev() {
+                let _ = fs::rename(original_file, file);
+            }
+            discard_files(staged_files);
+
+            return Err(CosprmError::io(file, source));
+        }
+
+        committed.push((file.clone(), original_file));
+    }
+
+    for (_, original_file) in &committed {
+        let _ = fs::remove_file(original_file);
+    }
+
+    Ok(())
+}
+
+fn discard_files(files: &[PathBuf]) {
+    for file in files {
+        let _ = fs::remove_file(file);
+    }
+}
+
+fn with_suffix(file: &str, suffix: &str) -> PathBuf {
+    PathBuf::from(format!("{}{}", file, suffix))
 }
 
 fn find_nucc_binary_type(chunk_filepath: &String) -> Option<NuccBinaryType> {
@@ -108,11 +402,19 @@ fn find_nucc_binary_type(chunk_filepath: &String) -> Option<NuccBinaryType> {
 fn collect_files(directory: &Path) -> Vec<String> {
     let mut files = Vec::new();
 
-    for entry in WalkDir::new(directory).follow_links(true) {
+    // Skip our own backups so they aren't loaded as param files
+    let walker = WalkDir::new(directory)
+        .follow_links(true)
+        .into_iter()
+        .filter_entry(|entry| entry.file_name() != BACKUP_DIR);
+
+    for entry in walker {
         match entry {
             Ok(entry) => {
                 // Also only collect .xfbin files
-                if entry.file_type().is_file() && entry.path().extension().unwrap() == "xfbin" {
+                if entry.file_type().is_file()
+                    && entry.path().extension().map_or(false, |ext| ext == "xfbin")
+                {
                     files.push(entry.path().to_path_buf());
                 }
             }
@@ -122,6 +424,6 @@ fn collect_files(directory: &Path) -> Vec<String> {
 
     files
         .iter()
-        .map(|path| path.to_str().unwrap().to_string())
+        .map(|path| path.to_string_lossy().to_string())
         .collect::<Vec<String>>()
 }
@@ -0,0 +1,71 @@
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{Map, Value};
+
+/// The rows of a serialized table, which is the first array in it
+pub fn table_entries(table: &Value) -> Option<&Vec<Value>> {
+    match table {
+        Value::Array(entries) => Some(entries),
+        Value::Object(fields) => fields
+            .get("entries")
+            .and_then(Value::as_array)
+            .or_else(|| fields.values().find_map(Value::as_array)),
+        _ => None,
+    }
+}
+
+pub fn table_entries_mut(table: &mut Value) -> Option<&mut Vec<Value>> {
+    match table {
+        Value::Array(entries) => Some(entries),
+        Value::Object(fields) => {
+            let name = if fields.get("entries").map_or(false, Value::is_array) {
+                "entries".to_string()
+            } else {
+                fields
+                    .iter()
+                    .find(|(_, value)| value.is_array())
+                    .map(|(name, _)| name.clone())?
+            };
+            fields.get_mut(&name).and_then(Value::as_array_mut)
+        }
+        _ => None,
+    }
+}
+
+/// The fields of an entry as nuccbin serializes them
+pub fn entry_fields<T: Serialize>(entry: &T) -> Map<String, Value> {
+    match serde_json::to_value(entry) {
+        Ok(Value::Object(fields)) => fields,
+        _ => Map::new(),
+    }
+}
+
+/// Set fields of an entry by name through its serialized form. Every field has to exist
+/// in the entry already and keep its type
+pub fn patch_entry<T: Serialize + DeserializeOwned>(
+    entry: &T,
+    fields: &Map<String, Value>,
+) -> Result<T, String> {
+    let mut patched = entry_fields(entry);
+
+    for (name, value) in fields {
+        match patched.get_mut(name) {
+            Some(field) => *field = value.clone(),
+            None => return Err(format!("the entries have no field {}", name)),
+        }
+    }
+
+    serde_json::from_value(Value::Object(patched)).map_err(|e| e.to_string())
+}
+
+/// The fields that differ between two entries, with their old and new values
+pub fn changed_fields<T: Serialize>(old: &T, new: &T) -> Vec<(String, Value, Value)> {
+    let old = entry_fields(old);
+    let new = entry_fields(new);
+
+    new.into_iter()
+        .filter_map(|(name, new_value)| {
+            let old_value = old.get(&name).cloned().unwrap_or(Value::Null);
+            (old_value != new_value).then(|| (name, old_value, new_value))
+        })
+        .collect()
+}
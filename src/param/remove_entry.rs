@@ -0,0 +1,385 @@
+use crate::cfg::{CostumeAddConfig, CostumeConfig};
+use crate::error::{CosprmError, Result};
+use nuccbin::{
+    nucc_binary::{
+        CharacterSelectParam, CostumeBreakParam, CostumeParam, MessageInfo, PlayerIcon,
+        PlayerSettingParam,
+    },
+    NuccBinaryType,
+};
+use std::collections::HashSet;
+
+use super::calc_crc32;
+use super::nucc_binary_handler::{
+    get_nucc_binaries_of_type_mut, get_nucc_binary_mut, get_table, NuccBinaries,
+};
+use super::report::*;
+
+// PlayerSettingParam is used to resolve the rows of the other tables, so it has to be removed last
+
+pub fn remove_message_info_entry(
+    nucc_binaries: &mut NuccBinaries,
+    cfg: &CostumeAddConfig,
+    report: &mut ChangeReport,
+) -> Result<()> {
+    // Ids that rows of other costumes still show, like a cha_id shared by a character's costumes
+    let referenced = referenced_message_ids(nucc_binaries);
+
+    for (key, nucc_binary) in
+        get_nucc_binaries_of_type_mut(nucc_binaries, NuccBinaryType::MessageInfo)
+    {
+        let language = key.language();
+
+        // Only the names the config gave the ids are ours, the game's own text stays
+        let names = cfg
+            .costumes
+            .iter()
+            .flat_map(|costume| {
+                [
+                    (&costume.cha_id, costume.char_name_for(language.as_deref())),
+                    (
+                        &costume.costume_id,
+                        costume.costume_name_for(language.as_deref()),
+                    ),
+                ]
+            })
+            .filter(|(id, _)| !id.is_empty() && !referenced.contains(id.as_str()))
+            .collect::<Vec<_>>();
+
+        let message_info = nucc_binary.table_mut::<MessageInfo>(NuccBinaryType::MessageInfo)?;
+
+        let count = message_info.entries.len();
+
+        message_info.entries.retain(|entry| {
+            let added = names
+                .iter()
+                .find(|(id, name)| calc_crc32(id) == entry.crc32 && entry.text3 == *name);
+
+            match added {
+                Some((id, _)) => {
+                    report.message_info.push(MessageInfoRow {
+                        language: language.clone(),
+                        id: id.to_string(),
+                        crc32: entry.crc32,
+                        text: entry.text3.clone(),
+                    });
+                    false
+                }
+                None => true,
+            }
+        });
+
+        if message_info.entries.len() != count {
+            nucc_binary.mark_dirty();
+        }
+    }
+
+    Ok(())
+}
+
+pub fn remove_player_setting_entry(
+    nucc_binaries: &mut NuccBinaries,
+    cfg: &CostumeAddConfig,
+    report: &mut ChangeReport,
+) -> Result<()> {
+    let param = get_nucc_binary_mut(nucc_binaries, NuccBinaryType::PlayerSettingParam).ok_or(
+        CosprmError::MissingTable {
+            nucc_type: NuccBinaryType::PlayerSettingParam,
+        },
+    )?;
+
+    let player_setting =
+        param.table_mut::<PlayerSettingParam>(NuccBinaryType::PlayerSettingParam)?;
+
+    let count = player_setting.entries.len();
+
+    player_setting.entries.retain(|entry| {
+        let matches = cfg.costumes.iter().any(|costume| {
+            entry.cha_b_id == costume.cha_id
+                && entry.duel_player_param_model_index == costume.model_index
+        });
+
+        if matches {
+            report.player_setting_param.push(PlayerSettingRow {
+                player_setting_id: entry.player_setting_id as u32,
+                searchcode: entry.searchcode.clone(),
+                cha_b_id: entry.cha_b_id.clone(),
+                characode_index: entry.characode_index as u32,
+                model_index: entry.duel_player_param_model_index,
+            });
+        }
+
+        !matches
+    });
+
+    if player_setting.entries.len() != count {
+        param.mark_dirty();
+    }
+
+    Ok(())
+}
+
+pub fn remove_costume_entry(
+    nucc_binaries: &mut NuccBinaries,
+    cfg: &CostumeAddConfig,
+    report: &mut ChangeReport,
+) -> Result<()> {
+    let player_setting =
+        get_table::<PlayerSettingParam>(nucc_binaries, NuccBinaryType::PlayerSettingParam)?.clone();
+
+    // The player_setting_ids of the costumes we added
+    let player_setting_ids = player_setting
+        .entries
+        .iter()
+        .filter(|entry| {
+            cfg.costumes.iter().any(|costume| {
+                entry.cha_b_id == costume.cha_id
+                    && entry.duel_player_param_model_index == costume.model_index
+            })
+        })
+        .map(|entry| entry.player_setting_id)
+        .collect::<Vec<_>>();
+
+    let param = get_nucc_binary_mut(nucc_binaries, NuccBinaryType::CostumeParam).ok_or(
+        CosprmError::MissingTable {
+            nucc_type: NuccBinaryType::CostumeParam,
+        },
+    )?;
+
+    let costume_param = param.table_mut::<CostumeParam>(NuccBinaryType::CostumeParam)?;
+
+    let count = costume_param.entries.len();
+
+    costume_param.entries.retain(|entry| {
+        let matches = player_setting_ids.contains(&entry.player_setting_id);
+
+        if matches {
+            report.costume_param.push(CostumeParamRow {
+                player_setting_id: entry.player_setting_id as u32,
+                color_index: entry.color_index,
+                costume_name: entry.costume_name.clone(),
+                costume_link: entry.costume_link.clone(),
+                price: entry.price,
+                unlock_condition: entry.unlock_condition,
+            });
+        }
+
+        !matches
+    });
+
+    if costume_param.entries.len() != count {
+        param.mark_dirty();
+    }
+
+    Ok(())
+}
+
+pub fn remove_icon_entry(
+    nucc_binaries: &mut NuccBinaries,
+    cfg: &CostumeAddConfig,
+    report: &mut ChangeReport,
+) -> Result<()> {
+    let player_setting =
+        get_table::<PlayerSettingParam>(nucc_binaries, NuccBinaryType::PlayerSettingParam)?.clone();
+
+    let param = get_nucc_binary_mut(nucc_binaries, NuccBinaryType::PlayerIcon).ok_or(
+        CosprmError::MissingTable {
+            nucc_type: NuccBinaryType::PlayerIcon,
+        },
+    )?;
+
+    let player_icon = param.table_mut::<PlayerIcon>(NuccBinaryType::PlayerIcon)?;
+
+    let count = player_icon.entries.len();
+
+    for costume in cfg.costumes.iter() {
+        let characode_index = match find_characode_index(&player_setting, costume) {
+            Some(characode_index) => characode_index,
+            None => continue,
+        };
+
+        player_icon.entries.retain(|entry| {
+            let matches = entry.characode_index as u32 == characode_index
+                && entry.duel_player_param_costume_index == costume.model_index;
+
+            if matches {
+                report.player_icon.push(PlayerIconRow {
+                    characode_index: entry.characode_index as u32,
+                    costume_index: entry.duel_player_param_costume_index,
+                    icon_id: entry.icon_id.clone(),
+                });
+            }
+
+            !matches
+        });
+    }
+
+    if player_icon.entries.len() != count {
+        param.mark_dirty();
+    }
+
+    Ok(())
+}
+
+pub fn remove_character_select_entry(
+    nucc_binaries: &mut NuccBinaries,
+    cfg: &CostumeAddConfig,
+    report: &mut ChangeReport,
+) -> Result<()> {
+    let player_setting =
+        get_table::<PlayerSettingParam>(nucc_binaries, NuccBinaryType::PlayerSettingParam)?.clone();
+
+    // The rows were added with the searchcodes of our PlayerSettingParam entries,
+    // other rows with the same costume_name belong to the game
+    let costumes = cfg
+        .costumes
+        .iter()
+        .flat_map(|costume| {
+            player_setting
+                .entries
+                .iter()
+                .filter(|entry| {
+                    entry.cha_b_id == costume.cha_id
+                        && entry.duel_player_param_model_index == costume.model_index
+                })
+                .map(move |entry| (entry.searchcode.clone(), &costume.costume_id))
+        })
+        .collect::<Vec<_>>();
+
+    let param = get_nucc_binary_mut(nucc_binaries, NuccBinaryType::CharacterSelectParam).ok_or(
+        CosprmError::MissingTable {
+            nucc_type: NuccBinaryType::CharacterSelectParam,
+        },
+    )?;
+
+    let character_select =
+        param.table_mut::<CharacterSelectParam>(NuccBinaryType::CharacterSelectParam)?;
+
+    let count = character_select.entries.len();
+
+    character_select.entries.retain(|entry| {
+        let matches = costumes.iter().any(|(searchcode, costume_id)| {
+            entry.searchcode == *searchcode && entry.costume_name == **costume_id
+        });
+
+        if matches {
+            report.character_select_param.push(CharacterSelectRow {
+                page_index: entry.page_index as u32,
+                slot_index: entry.slot_index as u32,
+                costume_slot_index: entry.costume_slot_index,
+                searchcode: entry.searchcode.clone(),
+                costume_name: entry.costume_name.clone(),
+            });
+        }
+
+        !matches
+    });
+
+    if character_select.entries.len() != count {
+        param.mark_dirty();
+    }
+
+    Ok(())
+}
+
+pub fn remove_costume_break_entry(
+    nucc_binaries: &mut NuccBinaries,
+    cfg: &CostumeAddConfig,
+    report: &mut ChangeReport,
+) -> Result<()> {
+    let player_setting =
+        get_table::<PlayerSettingParam>(nucc_binaries, NuccBinaryType::PlayerSettingParam)?.clone();
+
+    let param = get_nucc_binary_mut(nucc_binaries, NuccBinaryType::CostumeBreakParam).ok_or(
+        CosprmError::MissingTable {
+            nucc_type: NuccBinaryType::CostumeBreakParam,
+        },
+    )?;
+
+    let costume_break = param.table_mut::<CostumeBreakParam>(NuccBinaryType::CostumeBreakParam)?;
+
+    let count = costume_break.entries.len();
+
+    for costume in cfg.costumes.iter() {
+        let characode_index = match find_characode_index(&player_setting, costume) {
+            Some(characode_index) => characode_index,
+            None => continue,
+        };
+
+        costume_break.entries.retain(|entry| {
+            let matches = entry.characode_index as u32 == characode_index
+                && entry.costume_index == costume.model_index as u32;
+
+            if matches {
+                report.costume_break_param.push(CostumeBreakRow {
+                    characode_index: entry.characode_index as u32,
+                    costume_index: entry.costume_index,
+                });
+            }
+
+            !matches
+        });
+    }
+
+    if costume_break.entries.len() != count {
+        param.mark_dirty();
+    }
+
+    Ok(())
+}
+
+/// The message ids the rows of PlayerSettingParam, CostumeParam and CharacterSelectParam show
+fn referenced_message_ids(nucc_binaries: &NuccBinaries) -> HashSet<String> {
+    let mut ids = HashSet::new();
+
+    if let Ok(player_setting) =
+        get_table::<PlayerSettingParam>(nucc_binaries, NuccBinaryType::PlayerSettingParam)
+    {
+        ids.extend(
+            player_setting
+                .entries
+                .iter()
+                .map(|entry| entry.cha_b_id.clone()),
+        );
+    }
+
+    if let Ok(costume_param) =
+        get_table::<CostumeParam>(nucc_binaries, NuccBinaryType::CostumeParam)
+    {
+        ids.extend(
+            costume_param
+                .entries
+                .iter()
+                .map(|entry| entry.costume_name.clone()),
+        );
+    }
+
+    if let Ok(character_select) =
+        get_table::<CharacterSelectParam>(nucc_binaries, NuccBinaryType::CharacterSelectParam)
+    {
+        ids.extend(
+            character_select
+                .entries
+                .iter()
+                .map(|entry| entry.costume_name.clone()),
+        );
+    }
+
+    ids
+}
+
+/// Find the characode index of a costume from the entry we added for it. Costumes without
+/// one were never installed, so the rows at their model index belong to something else
+fn find_characode_index(
+    player_setting: &PlayerSettingParam,
+    costume: &CostumeConfig,
+) -> Option<u32> {
+    player_setting
+        .entries
+        .iter()
+        .find(|entry| {
+            entry.cha_b_id == costume.cha_id
+                && entry.duel_player_param_model_index == costume.model_index
+        })
+        .map(|entry| entry.characode_index as u32)
+}
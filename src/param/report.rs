@@ -0,0 +1,192 @@
+use crate::error::{CosprmError, Result};
+use serde::Serialize;
+
+/// Every row that was added to or removed from the param tables, grouped per table
+#[derive(Debug, Default, Serialize)]
+pub struct ChangeReport {
+    pub message_info: Vec<MessageInfoRow>,
+    pub player_setting_param: Vec<PlayerSettingRow>,
+    pub costume_param: Vec<CostumeParamRow>,
+    pub player_icon: Vec<PlayerIconRow>,
+    pub character_select_param: Vec<CharacterSelectRow>,
+    pub costume_break_param: Vec<CostumeBreakRow>,
+    /// Rows of the tables handled beyond the six above, e.g. DuelPlayerParam
+    pub other: Vec<OtherTableRow>,
+    /// Fields of existing entries that were changed to match the config
+    pub updated: Vec<UpdateRow>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MessageInfoRow {
+    pub language: Option<String>,
+    pub id: String,
+    pub crc32: [u8; 4],
+    pub text: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PlayerSettingRow {
+    pub player_setting_id: u32,
+    pub searchcode: String,
+    pub cha_b_id: String,
+    pub characode_index: u32,
+    pub model_index: i32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CostumeParamRow {
+    pub player_setting_id: u32,
+    pub color_index: u32,
+    pub costume_name: String,
+    pub costume_link: String,
+    pub price: u32,
+    pub unlock_condition: u32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PlayerIconRow {
+    pub characode_index: u32,
+    pub costume_index: i32,
+    pub icon_id: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CharacterSelectRow {
+    pub page_index: u32,
+    pub slot_index: u32,
+    pub costume_slot_index: u32,
+    pub searchcode: String,
+    pub costume_name: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CostumeBreakRow {
+    pub characode_index: u32,
+    pub costume_index: u32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OtherTableRow {
+    pub table: String,
+    pub entry: String,
+    pub description: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UpdateRow {
+    pub table: String,
+    pub entry: String,
+    pub field: String,
+    pub old: String,
+    pub new: String,
+}
+
+impl ChangeReport {
+    pub fn is_empty(&self) -> bool {
+        self.message_info.is_empty()
+            && self.player_setting_param.is_empty()
+            && self.costume_param.is_empty()
+            && self.player_icon.is_empty()
+            && self.character_select_param.is_empty()
+            && self.costume_break_param.is_empty()
+            && self.other.is_empty()
+            && self.updated.is_empty()
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        serde_json::to_string_pretty(self)
+            .map_err(|source| CosprmError::json_write("the change report", source))
+    }
+
+    /// Print a readable summary of every row, one table at a time
+    pub fn print(&self) {
+        if self.is_empty() {
+            println!("No entries.");
+            return;
+        }
+
+        print_table("MessageInfo", &self.message_info, |row| {
+            format!(
+                "[{}] {} {:02x?} \"{}\"",
+                row.language.as_deref().unwrap_or("?"),
+                row.id,
+                row.crc32,
+                row.text
+            )
+        });
+
+        print_table("PlayerSettingParam", &self.player_setting_param, |row| {
+            format!(
+                "id {} searchcode {} cha_b_id {} characode_index {} model_index {}",
+                row.player_setting_id,
+                row.searchcode,
+                row.cha_b_id,
+                row.characode_index,
+                row.model_index
+            )
+        });
+
+        print_table("CostumeParam", &self.costume_param, |row| {
+            format!(
+                "player_setting_id {} color {} {} {} price {} unlock_condition {}",
+                row.player_setting_id,
+                row.color_index,
+                row.costume_name,
+                row.costume_link,
+                row.price,
+                row.unlock_condition
+            )
+        });
+
+        print_table("PlayerIcon", &self.player_icon, |row| {
+            format!(
+                "characode_index {} costume_index {} icon {}",
+                row.characode_index, row.costume_index, row.icon_id
+            )
+        });
+
+        print_table(
+            "CharacterSelectParam",
+            &self.character_select_param,
+            |row| {
+                format!(
+                    "page {} slot {} costume slot {} searchcode {} {}",
+                    row.page_index,
+                    row.slot_index,
+                    row.costume_slot_index,
+                    row.searchcode,
+                    row.costume_name
+                )
+            },
+        );
+
+        print_table("CostumeBreakParam", &self.costume_break_param, |row| {
+            format!(
+                "characode_index {} costume_index {}",
+                row.characode_index, row.costume_index
+            )
+        });
+
+        print_table("Other tables", &self.other, |row| {
+            format!("{} {}: {}", row.table, row.entry, row.description)
+        });
+
+        print_table("Updated", &self.updated, |row| {
+            format!(
+                "{} {} {}: \"{}\" -> \"{}\"",
+                row.table, row.entry, row.field, row.old, row.new
+            )
+        });
+    }
+}
+
+fn print_table<T>(name: &str, rows: &[T], format_row: impl Fn(&T) -> String) {
+    if rows.is_empty() {
+        return;
+    }
+
+    println!("{} ({} entries):", name, rows.len());
+    for row in rows {
+        println!("    {}", format_row(row));
+    }
+}
@@ -0,0 +1,109 @@
+use crate::error::{CosprmError, Result};
+use nuccbin::nucc_binary::PlayerSettingParam;
+
+/// Searchcodes are the 4 character characode followed by a two digit number (e.g. "3mnt09")
+const CHARACODE_LEN: usize = 4;
+const MAX_SEARCHCODE_NUMBER: u32 = 99;
+
+/// Split a searchcode into its characode and number, if it ends in a number
+pub fn parse_searchcode(searchcode: &str) -> Option<(&str, u32)> {
+    if searchcode.len() <= CHARACODE_LEN || !searchcode.is_char_boundary(CHARACODE_LEN) {
+        return None;
+    }
+
+    let (characode, number) = searchcode.split_at(CHARACODE_LEN);
+
+    if !number.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+
+    number.parse::<u32>().ok().map(|number| (characode, number))
+}
+
+pub fn format_searchcode(characode: &str, number: u32) -> String {
+    format!("{}{:02}", characode, number)
+}
+
+/// The next unused searchcode for a characode, one past the highest number in PlayerSettingParam.
+/// Reserved searchcodes are treated as used
+pub fn next_searchcode(
+    player_setting: &PlayerSettingParam,
+    characode: &str,
+    reserved: &[&str],
+) -> Result<String> {
+    let searchcodes = player_setting
+        .entries
+        .iter()
+        .map(|entry| entry.searchcode.as_str())
+        .chain(reserved.iter().copied());
+
+    next_searchcode_after(searchcodes, characode)
+}
+
+/// One past the highest number of the characode's searchcodes
+fn next_searchcode_after<'a>(
+    searchcodes: impl IntoIterator<Item = &'a str>,
+    characode: &str,
+) -> Result<String> {
+    let highest = searchcodes
+        .into_iter()
+        .filter_map(parse_searchcode)
+        .filter(|(entry_characode, _)| *entry_characode == characode)
+        .map(|(_, number)| number)
+        .max();
+
+    let next = highest.map_or(0, |number| number + 1);
+
+    if next > MAX_SEARCHCODE_NUMBER {
+        return Err(CosprmError::SearchcodesExhausted {
+            characode: characode.to_string(),
+        });
+    }
+
+    Ok(format_searchcode(characode, next))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_two_digit_numbers() {
+        assert_eq!(parse_searchcode("3mnt09"), Some(("3mnt", 9)));
+        assert_eq!(parse_searchcode("3mnt10"), Some(("3mnt", 10)));
+    }
+
+    #[test]
+    fn rejects_suffixes_that_are_not_numbers() {
+        assert_eq!(parse_searchcode("3mnt0a"), None);
+        assert_eq!(parse_searchcode("3mntab"), None);
+        assert_eq!(parse_searchcode("3mnt"), None);
+    }
+
+    #[test]
+    fn counts_past_nine() {
+        let next = next_searchcode_after(["3mnt00", "3mnt09"], "3mnt").unwrap();
+        assert_eq!(next, "3mnt10");
+    }
+
+    #[test]
+    fn skips_gaps_and_other_characters() {
+        let next = next_searchcode_after(["3mnt00", "3mnt05", "2nrt20", "3mntxx"], "3mnt").unwrap();
+        assert_eq!(next, "3mnt06");
+    }
+
+    #[test]
+    fn starts_at_zero() {
+        let next = next_searchcode_after(["2nrt00"], "3mnt").unwrap();
+        assert_eq!(next, "3mnt00");
+    }
+
+    #[test]
+    fn fails_past_ninety_nine() {
+        let result = next_searchcode_after(["3mnt99"], "3mnt");
+        assert!(matches!(
+            result,
+            Err(CosprmError::SearchcodesExhausted { characode }) if characode == "3mnt"
+        ));
+    }
+}
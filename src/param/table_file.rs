@@ -0,0 +1,326 @@
+use crate::error::{CosprmError, Result};
+use nuccbin::{
+    nucc_binary::{
+        CharacterSelectParam, CostumeBreakParam, CostumeParam, DuelPlayerParam, MessageInfo,
+        NuccBinaryParsed, NuccBinaryParsedDeserializer, PlayerIcon, PlayerSettingParam,
+    },
+    NuccBinaryType,
+};
+use serde_json::{Map, Value};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use super::message_dictionary::MessageDictionary;
+use super::nucc_binary_handler::{NuccBinaries, NuccBinaryKey};
+use super::patch::{table_entries, table_entries_mut};
+
+/// The column added to exported MessageInfo rows with the id their crc32 belongs to.
+/// It's only there to be read and is dropped again on import
+pub const MESSAGE_ID_COLUMN: &str = "message_id";
+
+/// The file format tables are exported to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TableFormat {
+    /// The whole table as nuccbin serializes it
+    Json,
+    /// One row per entry. Strings are written as is, every other value as JSON
+    Csv,
+}
+
+impl TableFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            TableFormat::Json => "json",
+            TableFormat::Csv => "csv",
+        }
+    }
+
+    fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "json" => Some(TableFormat::Json),
+            "csv" => Some(TableFormat::Csv),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for TableFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "json" => Ok(TableFormat::Json),
+            "csv" => Ok(TableFormat::Csv),
+            _ => Err(format!("unknown table format {}, expected json or csv", s)),
+        }
+    }
+}
+
+impl fmt::Display for TableFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.extension())
+    }
+}
+
+/// The file name of a table without its extension, e.g. "PlayerSettingParam" or "MessageInfo.eng"
+pub fn table_file_stem(key: &NuccBinaryKey) -> String {
+    match key.language() {
+        Some(language) if key.nucc_type == NuccBinaryType::MessageInfo => {
+            format!("{:?}.{}", key.nucc_type, language)
+        }
+        _ => format!("{:?}", key.nucc_type),
+    }
+}
+
+/// Write every loaded table to its own file in `output`, returning the paths of the files.
+/// MessageInfo rows get the id of their crc32 from `message_ids`, empty when it's unknown
+pub fn export_tables(
+    nucc_binaries: &NuccBinaries,
+    output: &Path,
+    format: TableFormat,
+    message_ids: &MessageDictionary,
+) -> Result<Vec<String>> {
+    fs::create_dir_all(output).map_err(|source| CosprmError::io(output, source))?;
+
+    let mut keys = nucc_binaries.keys().collect::<Vec<_>>();
+    keys.sort_by(|a, b| (&a.filepath, &a.chunk_filepath).cmp(&(&b.filepath, &b.chunk_filepath)));
+
+    let mut files = Vec::new();
+
+    for key in keys {
+        let file = output.join(format!("{}.{}", table_file_stem(key), format.extension()));
+        let mut table = table_value(key, nucc_binaries[key].parsed.as_ref())?;
+
+        if key.nucc_type == NuccBinaryType::MessageInfo {
+            add_message_ids(&mut table, message_ids);
+        }
+
+        match format {
+            TableFormat::Json => {
+                let json_str = serde_json::to_string_pretty(&table)
+                    .map_err(|source| CosprmError::json_write(&file, source))?;
+                fs::write(&file, json_str).map_err(|source| CosprmError::io(&file, source))?;
+            }
+            TableFormat::Csv => write_csv(&file, &table)?,
+        }
+
+        files.push(file.display().to_string());
+    }
+
+    Ok(files)
+}
+
+/// Replace the loaded tables with the edited files in `input` and mark the ones that changed
+/// as dirty. Every file is checked before any table is replaced. Returns the files that changed
+pub fn import_tables(nucc_binaries: &mut NuccBinaries, input: &Path) -> Result<Vec<String>> {
+    let read_dir = fs::read_dir(input).map_err(|source| CosprmError::io(input, source))?;
+
+    let mut files = read_dir
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file() && TableFormat::from_path(path).is_some())
+        .collect::<Vec<_>>();
+    files.sort();
+
+    let mut imported = Vec::new();
+
+    for file in &files {
+        let stem = file
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        let key = nucc_binaries
+            .keys()
+            .find(|key| table_file_stem(key) == stem)
+            .cloned()
+            .ok_or_else(|| table_error(file, "the file name doesn't match a loaded table"))?;
+
+        let current = table_value(&key, nucc_binaries[&key].parsed.as_ref())?;
+
+        let mut edited = match TableFormat::from_path(file) {
+            Some(TableFormat::Csv) => read_csv(file, &current)?,
+            _ => {
+                let json_str =
+                    fs::read_to_string(file).map_err(|source| CosprmError::io(file, source))?;
+                serde_json::from_str(&json_str).map_err(|e| table_error(file, e))?
+            }
+        };
+
+        if key.nucc_type == NuccBinaryType::MessageInfo {
+            strip_message_ids(&mut edited);
+        }
+
+        if edited == current {
+            continue;
+        }
+
+        let bytes = serde_json::to_vec(&edited).map_err(|e| table_error(file, e))?;
+        check_table(key.nucc_type, &bytes).map_err(|e| table_error(file, e))?;
+
+        imported.push((key, bytes, file.display().to_string()));
+    }
+
+    let mut changed_files = Vec::new();
+
+    for (key, bytes, file) in imported {
+        if let Some(nucc_binary) = nucc_binaries.get_mut(&key) {
+            nucc_binary.parsed = NuccBinaryParsedDeserializer(key.nucc_type, bytes).into();
+            nucc_binary.mark_dirty();
+            changed_files.push(file);
+        }
+    }
+
+    Ok(changed_files)
+}
+
+fn table_value(key: &NuccBinaryKey, parsed: &dyn NuccBinaryParsed) -> Result<Value> {
+    serde_json::from_slice(&parsed.serialize()).map_err(|e| CosprmError::XfbinRead {
+        path: key.filepath.clone(),
+        message: format!("{:?} could not be serialized: {}", key.nucc_type, e),
+    })
+}
+
+/// Make sure the JSON deserializes into the table before it's handed to nuccbin
+fn check_table(nucc_type: NuccBinaryType, bytes: &[u8]) -> serde_json::Result<()> {
+    match nucc_type {
+        NuccBinaryType::MessageInfo => serde_json::from_slice::<MessageInfo>(bytes).map(|_| ()),
+        NuccBinaryType::PlayerSettingParam => {
+            serde_json::from_slice::<PlayerSettingParam>(bytes).map(|_| ())
+        }
+        NuccBinaryType::CostumeParam => serde_json::from_slice::<CostumeParam>(bytes).map(|_| ()),
+        NuccBinaryType::PlayerIcon => serde_json::from_slice::<PlayerIcon>(bytes).map(|_| ()),
+        NuccBinaryType::CharacterSelectParam => {
+            serde_json::from_slice::<CharacterSelectParam>(bytes).map(|_| ())
+        }
+        NuccBinaryType::CostumeBreakParam => {
+            serde_json::from_slice::<CostumeBreakParam>(bytes).map(|_| ())
+        }
+        NuccBinaryType::DuelPlayerParam => {
+            serde_json::from_slice::<DuelPlayerParam>(bytes).map(|_| ())
+        }
+        _ => Ok(()),
+    }
+}
+
+fn add_message_ids(table: &mut Value, message_ids: &MessageDictionary) {
+    for row in table_entries_mut(table).into_iter().flatten() {
+        if let Value::Object(fields) = row {
+            let id = fields
+                .get("crc32")
+                .and_then(|crc32| serde_json::from_value::<[u8; 4]>(crc32.clone()).ok())
+                .and_then(|crc32| message_ids.lookup(&crc32))
+                .unwrap_or_default()
+                .to_string();
+
+            fields.insert(MESSAGE_ID_COLUMN.to_string(), Value::String(id));
+        }
+    }
+}
+
+fn strip_message_ids(table: &mut Value) {
+    for row in table_entries_mut(table).into_iter().flatten() {
+        if let Value::Object(fields) = row {
+            fields.remove(MESSAGE_ID_COLUMN);
+        }
+    }
+}
+
+fn write_csv(file: &Path, table: &Value) -> Result<()> {
+    let rows = table_entries(table)
+        .ok_or_else(|| table_error(file, "the table has no entries"))?
+        .iter()
+        .filter_map(Value::as_object)
+        .collect::<Vec<_>>();
+
+    let columns = rows
+        .first()
+        .map(|row| row.keys().cloned().collect::<Vec<_>>())
+        .unwrap_or_default();
+
+    let mut writer = csv::Writer::from_path(file).map_err(|e| table_error(file, e))?;
+
+    writer
+        .write_record(&columns)
+        .map_err(|e| table_error(file, e))?;
+
+    for row in rows {
+        let cells = columns
+            .iter()
+            .map(|column| match row.get(column) {
+                Some(Value::String(text)) => text.clone(),
+                Some(value) => value.to_string(),
+                None => String::new(),
+            })
+            .collect::<Vec<_>>();
+
+        writer
+            .write_record(&cells)
+            .map_err(|e| table_error(file, e))?;
+    }
+
+    writer
+        .flush()
+        .map_err(|source| CosprmError::io(file, source))
+}
+
+/// Build the edited table from a CSV file. The rows replace the entries of the current table,
+/// and each cell takes the type the column has in the current entries
+fn read_csv(file: &Path, current: &Value) -> Result<Value> {
+    let mut reader = csv::Reader::from_path(file).map_err(|e| table_error(file, e))?;
+
+    let columns = reader
+        .headers()
+        .map_err(|e| table_error(file, e))?
+        .iter()
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+
+    let template = table_entries(current)
+        .and_then(|entries| entries.first())
+        .and_then(Value::as_object)
+        .cloned()
+        .unwrap_or_default();
+
+    let mut rows = Vec::new();
+
+    for (i, record) in reader.records().enumerate() {
+        let record = record.map_err(|e| table_error(file, e))?;
+
+        let mut row = Map::new();
+
+        for (column, cell) in columns.iter().zip(record.iter()) {
+            let value = match template.get(column) {
+                Some(Value::String(_)) => Value::String(cell.to_string()),
+                Some(_) => serde_json::from_str(cell).map_err(|e| {
+                    table_error(file, format!("row {} column {}: {}", i + 1, column, e))
+                })?,
+                // Columns the current table doesn't have are guessed
+                None => {
+                    serde_json::from_str(cell).unwrap_or_else(|_| Value::String(cell.to_string()))
+                }
+            };
+
+            row.insert(column.clone(), value);
+        }
+
+        rows.push(Value::Object(row));
+    }
+
+    let mut edited = current.clone();
+
+    let entries = table_entries_mut(&mut edited)
+        .ok_or_else(|| table_error(file, "the table has no entries"))?;
+    *entries = rows;
+
+    Ok(edited)
+}
+
+fn table_error(file: &Path, message: impl ToString) -> CosprmError {
+    CosprmError::InvalidTableFile {
+        path: file.display().to_string(),
+        message: message.to_string(),
+    }
+}
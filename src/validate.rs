@@ -0,0 +1,343 @@
+use crate::cfg::{CostumeAddConfig, CostumeConfig};
+use crate::error::CosprmError;
+use crate::lock::IdLock;
+use crate::param::add_entry::costume_break_overrides;
+use crate::param::calc_crc32;
+use crate::param::message_dictionary::MessageDictionary;
+use crate::param::nucc_binary_handler::{get_message_infos, get_table, NuccBinaries};
+use crate::param::patch::patch_entry;
+use nuccbin::nucc_binary::{
+    CharacterSelectParam, CostumeBreakParam, CostumeParam, PlayerSettingParam,
+};
+use nuccbin::NuccBinaryType;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::fmt;
+
+/// Most colors a costume can have in the character select screen
+pub const MAX_COLOR_COUNT: i32 = 4;
+
+/// A problem with a costume in the config
+#[derive(Debug, Clone, Serialize)]
+pub struct ValidationIssue {
+    pub costume: String,
+    pub field: String,
+    pub message: String,
+}
+
+impl fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}: {}", self.costume, self.field, self.message)
+    }
+}
+
+/// Check the config on its own, without looking at the param tables
+pub fn validate_config(cfg: &CostumeAddConfig) -> Vec<ValidationIssue> {
+    let mut issues = Vec::new();
+    // The ids of the config, to catch two of them sharing a MessageInfo crc32
+    let mut message_ids = MessageDictionary::default();
+
+    for (i, costume) in cfg.costumes.iter().enumerate() {
+        let mut issue = |field: &str, message: String| {
+            issues.push(ValidationIssue {
+                costume: costume.label(),
+                field: field.to_string(),
+                message,
+            })
+        };
+
+        for (field, code) in [
+            ("characode", &costume.characode),
+            ("modelcode", &costume.modelcode),
+        ] {
+            if code.chars().count() != 4 {
+                issue(field, format!("\"{}\" must be 4 characters", code));
+            }
+        }
+
+        // An empty iconcode means the costume doesn't get an icon
+        if !costume.iconcode.is_empty() && costume.iconcode.chars().count() != 4 {
+            issue(
+                "iconcode",
+                format!("\"{}\" must be 4 characters or empty", costume.iconcode),
+            );
+        }
+
+        let broken_modelcode = costume
+            .costume_break
+            .as_ref()
+            .and_then(|costume_break| costume_break.broken_modelcode.as_ref());
+
+        if let Some(broken_modelcode) = broken_modelcode {
+            if broken_modelcode.chars().count() != 4 {
+                issue(
+                    "costume_break.broken_modelcode",
+                    format!("\"{}\" must be 4 characters", broken_modelcode),
+                );
+            }
+        }
+
+        // Empty ids and a model index of 0 are picked when installing
+        if !costume.cha_id.is_empty() && !is_numbered_id(&costume.cha_id, "c_cha_") {
+            issue(
+                "cha_id",
+                format!("\"{}\" must look like c_cha_NNN", costume.cha_id),
+            );
+        }
+
+        if !costume.costume_id.is_empty() && !is_numbered_id(&costume.costume_id, "c_costume_") {
+            issue(
+                "costume_id",
+                format!("\"{}\" must look like c_costume_NNN", costume.costume_id),
+            );
+        }
+
+        for (field, id) in [
+            ("cha_id", &costume.cha_id),
+            ("costume_id", &costume.costume_id),
+        ] {
+            if let Some(known) = message_ids.insert(id) {
+                issue(
+                    field,
+                    format!("{} has the same crc32 as {} in the config", id, known),
+                );
+            }
+        }
+
+        if costume.color_count < 1 || costume.color_count > MAX_COLOR_COUNT {
+            issue(
+                "color_count",
+                format!(
+                    "{} must be between 1 and {}",
+                    costume.color_count, MAX_COLOR_COUNT
+                ),
+            );
+        }
+
+        // Model index 0 is the character's base costume, so it can't be set explicitly
+        if costume.model_index < 0 {
+            issue(
+                "model_index",
+                format!(
+                    "{} can't be negative, 0 picks a free one",
+                    costume.model_index
+                ),
+            );
+        }
+
+        let duplicate = cfg.costumes[..i].iter().find(|other| {
+            costume.model_index != 0
+                && other.characode == costume.characode
+                && other.model_index == costume.model_index
+        });
+
+        if let Some(other) = duplicate {
+            issue(
+                "model_index",
+                format!("{} is also used by {}", costume.model_index, other.cha_id),
+            );
+        }
+    }
+
+    issues
+}
+
+/// Check the config against the loaded param tables, and its message ids against the known ones.
+/// Message ids the lockfile records for a costume, or its installed rows show, are its own
+pub fn validate_against_tables(
+    cfg: &CostumeAddConfig,
+    nucc_binaries: &NuccBinaries,
+    message_ids: &MessageDictionary,
+    lock: &IdLock,
+) -> Vec<ValidationIssue> {
+    let mut issues = Vec::new();
+
+    // The ids of most game messages aren't known, so every crc32 with text counts as taken
+    let existing_crc32s = get_message_infos(nucc_binaries)
+        .into_iter()
+        .flat_map(|(_, message_info)| message_info.entries.iter().map(|entry| entry.crc32))
+        .collect::<HashSet<_>>();
+
+    // MessageInfo rows are found by crc32 alone, so a colliding id would take over the other's text
+    for costume in cfg.costumes.iter() {
+        for (field, id) in [
+            ("cha_id", &costume.cha_id),
+            ("costume_id", &costume.costume_id),
+        ] {
+            let message = if let Some(known) = message_ids.collision(id) {
+                format!(
+                    "{} has the same MessageInfo crc32 as the existing id {}",
+                    id, known
+                )
+            } else if !id.is_empty()
+                && existing_crc32s.contains(&calc_crc32(id))
+                && !recorded_in_lock(lock, costume, id)
+                && !shown_by_costume(nucc_binaries, costume, id)
+            {
+                format!(
+                    "{} already has MessageInfo text that doesn't belong to this costume",
+                    id
+                )
+            } else {
+                continue;
+            };
+
+            issues.push(ValidationIssue {
+                costume: costume.label(),
+                field: field.to_string(),
+                message,
+            });
+        }
+    }
+
+    // The costume break fields are checked against an existing entry, since only nuccbin knows them
+    let costume_break_entry =
+        get_table::<CostumeBreakParam>(nucc_binaries, NuccBinaryType::CostumeBreakParam)
+            .ok()
+            .and_then(|costume_break| costume_break.entries.first());
+
+    if let Some(costume_break_entry) = costume_break_entry {
+        for costume in cfg.costumes.iter() {
+            if !costume.costume_break_enabled() {
+                continue;
+            }
+
+            let checked = costume_break_overrides(costume)
+                .map_err(|e| match e {
+                    CosprmError::InvalidOverride { message, .. } => message,
+                    e => e.to_string(),
+                })
+                .and_then(|overrides| patch_entry(costume_break_entry, &overrides));
+
+            if let Err(message) = checked {
+                issues.push(ValidationIssue {
+                    costume: costume.label(),
+                    field: "costume_break".to_string(),
+                    message,
+                });
+            }
+        }
+    }
+
+    let player_setting = match get_table::<PlayerSettingParam>(
+        nucc_binaries,
+        NuccBinaryType::PlayerSettingParam,
+    ) {
+        Ok(player_setting) => player_setting,
+        Err(e) => {
+            issues.push(ValidationIssue {
+                costume: "config".to_string(),
+                field: "tables".to_string(),
+                message: e.to_string(),
+            });
+            return issues;
+        }
+    };
+
+    for costume in cfg.costumes.iter() {
+        let character_entries = player_setting
+            .entries
+            .iter()
+            .filter(|entry| entry.searchcode.starts_with(&costume.characode))
+            .collect::<Vec<_>>();
+
+        if character_entries.is_empty() {
+            issues.push(ValidationIssue {
+                costume: costume.label(),
+                field: "characode".to_string(),
+                message: format!(
+                    "{} has no PlayerSettingParam entries",
+                    costume.characode
+                ),
+            });
+            continue;
+        }
+
+        // Entries with our own cha_id are this costume from an earlier run
+        let taken = character_entries.iter().find(|entry| {
+            costume.model_index != 0
+                && entry.duel_player_param_model_index == costume.model_index
+                && entry.cha_b_id != costume.cha_id
+        });
+
+        if let Some(entry) = taken {
+            issues.push(ValidationIssue {
+                costume: costume.label(),
+                field: "model_index".to_string(),
+                message: format!(
+                    "{} is already used by PlayerSettingParam entry {} ({})",
+                    costume.model_index, entry.searchcode, entry.cha_b_id
+                ),
+            });
+        }
+    }
+
+    issues
+}
+
+/// Whether the costume was installed with the id before, according to the lockfile
+fn recorded_in_lock(lock: &IdLock, costume: &CostumeConfig, id: &str) -> bool {
+    let assigned = lock.get_assigned(&costume.lock_key()).map_or(false, |ids| {
+        ids.cha_id.as_deref() == Some(id) || ids.costume_id.as_deref() == Some(id)
+    });
+
+    // Installed costumes are recorded with their player_setting_id
+    assigned || lock.get(&costume.lock_key()).is_some()
+}
+
+/// Whether the rows the costume was installed with show the id. They're found the way `cosprm remove`
+/// finds them, so costumes installed without a lockfile keep their ids
+fn shown_by_costume(nucc_binaries: &NuccBinaries, costume: &CostumeConfig, id: &str) -> bool {
+    let player_setting =
+        match get_table::<PlayerSettingParam>(nucc_binaries, NuccBinaryType::PlayerSettingParam) {
+            Ok(player_setting) => player_setting,
+            Err(_) => return false,
+        };
+
+    let own_entries = player_setting
+        .entries
+        .iter()
+        .filter(|entry| {
+            entry.cha_b_id == costume.cha_id
+                && entry.duel_player_param_model_index == costume.model_index
+        })
+        .collect::<Vec<_>>();
+
+    if own_entries.is_empty() {
+        return false;
+    }
+
+    if id == costume.cha_id {
+        return true;
+    }
+
+    let in_costume_param = get_table::<CostumeParam>(nucc_binaries, NuccBinaryType::CostumeParam)
+        .map_or(false, |costume_param| {
+            costume_param.entries.iter().any(|entry| {
+                entry.costume_name == id
+                    && own_entries
+                        .iter()
+                        .any(|own| own.player_setting_id == entry.player_setting_id)
+            })
+        });
+
+    let in_character_select =
+        get_table::<CharacterSelectParam>(nucc_binaries, NuccBinaryType::CharacterSelectParam)
+            .map_or(false, |character_select| {
+                character_select.entries.iter().any(|entry| {
+                    entry.costume_name == id
+                        && own_entries
+                            .iter()
+                            .any(|own| own.searchcode == entry.searchcode)
+                })
+            });
+
+    in_costume_param || in_character_select
+}
+
+fn is_numbered_id(id: &str, prefix: &str) -> bool {
+    id.strip_prefix(prefix)
+        .map_or(false, |number| {
+            !number.is_empty() && number.chars().all(|c| c.is_ascii_digit())
+        })
+}